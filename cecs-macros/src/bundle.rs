@@ -0,0 +1,123 @@
+use quote::quote;
+use syn::{parse_quote, Data, DeriveInput, Fields};
+
+pub(crate) fn derive_bundle_impl(mut input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.clone(),
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "Bundle can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Bundle can only be derived for structs with named fields",
+            ))
+        }
+    };
+
+    let mut compute_hash = Vec::with_capacity(fields.len());
+    let mut can_insert = Vec::with_capacity(fields.len());
+    let mut insert = Vec::with_capacity(fields.len());
+    let mut extend = Vec::with_capacity(fields.len());
+    let mut component_ids = Vec::with_capacity(fields.len());
+    // archetype hashes combine the component hashes with XOR, so duplicates would cancel out
+    let mut component_types = Vec::<String>::with_capacity(fields.len());
+    let where_clause = input.generics.make_where_clause();
+    for field in fields.iter() {
+        let name = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let mut nested = false;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("bundle")) {
+            attr.meta.require_path_only()?;
+            nested = true;
+        }
+        if nested {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: ::cecs::bundle::Bundle));
+            compute_hash.push(quote! {
+                let base = <#ty as ::cecs::bundle::Bundle>::compute_hash(base);
+            });
+            can_insert.push(quote! {
+                ::cecs::bundle::Bundle::can_insert(&self.#name, archetype)
+            });
+            insert.push(quote! {
+                ::cecs::bundle::Bundle::insert(self.#name, archetype, index)?;
+            });
+            extend.push(quote! {
+                let result = <#ty as ::cecs::bundle::Bundle>::extend(&result);
+            });
+            component_ids.push(quote! {
+                <#ty as ::cecs::bundle::Bundle>::component_ids(ids);
+            });
+        } else {
+            let ty_str = quote!(#ty).to_string();
+            if component_types.contains(&ty_str) {
+                return Err(syn::Error::new_spanned(
+                    ty,
+                    "Bundle fields must have distinct component types",
+                ));
+            }
+            component_types.push(ty_str);
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: ::cecs::Component));
+            compute_hash.push(quote! {
+                let base = <(#ty,) as ::cecs::bundle::Bundle>::compute_hash(base);
+            });
+            can_insert.push(quote! {
+                archetype.contains_column::<#ty>()
+            });
+            insert.push(quote! {
+                ::cecs::bundle::Bundle::insert((self.#name,), archetype, index)?;
+            });
+            extend.push(quote! {
+                let result = <(#ty,) as ::cecs::bundle::Bundle>::extend(&result);
+            });
+            component_ids.push(quote! {
+                ids.push(::std::any::TypeId::of::<#ty>());
+            });
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::cecs::bundle::Bundle for #ident #ty_generics #where_clause {
+            fn compute_hash(base: ::cecs::bundle::__private::TypeHash) -> ::cecs::bundle::__private::TypeHash {
+                #(#compute_hash)*
+                base
+            }
+
+            fn can_insert(&self, archetype: &::cecs::bundle::__private::ArchetypeStorage) -> bool {
+                true #(&& #can_insert)*
+            }
+
+            fn insert(
+                self,
+                archetype: &mut ::cecs::bundle::__private::ArchetypeStorage,
+                index: ::cecs::RowIndex,
+            ) -> ::cecs::WorldResult<()> {
+                #(#insert)*
+                Ok(())
+            }
+
+            fn extend(
+                archetype: &::cecs::bundle::__private::ArchetypeStorage,
+            ) -> ::cecs::bundle::__private::ArchetypeStorage {
+                let result = archetype.clone_empty();
+                #(#extend)*
+                result
+            }
+
+            fn component_ids(ids: &mut ::std::vec::Vec<::std::any::TypeId>) {
+                #(#component_ids)*
+            }
+        }
+    })
+}
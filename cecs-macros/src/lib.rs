@@ -0,0 +1,30 @@
+//! Derive macros for [cecs](https://github.com/caolo-game/cecs), use them via the `cecs` crate
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+mod bundle;
+mod system_param;
+
+/// Implement `Bundle` for a struct with named fields
+///
+/// Every field is inserted as a component, except fields marked `#[bundle]`, which must be
+/// `Bundle`s themselves and are inserted component by component.
+#[proc_macro_derive(Bundle, attributes(bundle))]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    bundle::derive_bundle_impl(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implement `SystemParam` for a struct whose fields are system parameters
+///
+/// The struct may have at most one lifetime parameter, which is used as the lifetime of the World
+/// borrow.
+#[proc_macro_derive(SystemParam)]
+pub fn derive_system_param(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    system_param::derive_system_param_impl(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,92 @@
+use quote::quote;
+use syn::{parse_quote, Data, DeriveInput, GenericParam, Lifetime};
+
+pub(crate) fn derive_system_param_impl(
+    input: DeriveInput,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "SystemParam can only be derived for structs",
+        ));
+    };
+
+    // the World lifetime is the struct's lifetime parameter, if it has one
+    let mut lifetimes = input.generics.lifetimes();
+    let (lifetime, mut generics): (Lifetime, _) = match (lifetimes.next(), lifetimes.next()) {
+        (None, _) => {
+            let lifetime: Lifetime = parse_quote!('__w);
+            let mut generics = input.generics.clone();
+            generics
+                .params
+                .insert(0, GenericParam::Lifetime(parse_quote!(#lifetime)));
+            (lifetime, generics)
+        }
+        (Some(l), None) => (l.lifetime.clone(), input.generics.clone()),
+        (Some(_), Some(extra)) => {
+            return Err(syn::Error::new_spanned(
+                extra,
+                "SystemParam structs may have at most one lifetime parameter",
+            ))
+        }
+    };
+
+    let members = data.fields.members().collect::<Vec<_>>();
+    let types = data.fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+    let where_clause = generics.make_where_clause();
+    for ty in types.iter() {
+        where_clause
+            .predicates
+            .push(parse_quote!(#ty: ::cecs::systems::SystemParam<#lifetime>));
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    Ok(quote! {
+        unsafe impl #impl_generics ::cecs::systems::SystemParam<#lifetime> for #ident #ty_generics #where_clause {
+            fn new(
+                db: &#lifetime ::cecs::World,
+                commands_index: usize,
+                ticks: ::cecs::systems::SystemTicks,
+            ) -> Self {
+                Self {
+                    #(
+                        #members: <#types as ::cecs::systems::SystemParam<#lifetime>>::new(
+                            db,
+                            commands_index,
+                            ticks,
+                        ),
+                    )*
+                }
+            }
+
+            fn components_mut(set: &mut ::std::collections::HashSet<::cecs::query::TypeDesc>) {
+                #(<#types as ::cecs::systems::SystemParam<#lifetime>>::components_mut(set);)*
+            }
+
+            fn components_const(set: &mut ::std::collections::HashSet<::cecs::query::TypeDesc>) {
+                #(<#types as ::cecs::systems::SystemParam<#lifetime>>::components_const(set);)*
+            }
+
+            fn resources_mut(set: &mut ::std::collections::HashSet<::cecs::query::TypeDesc>) {
+                #(<#types as ::cecs::systems::SystemParam<#lifetime>>::resources_mut(set);)*
+            }
+
+            fn resources_const(set: &mut ::std::collections::HashSet<::cecs::query::TypeDesc>) {
+                #(<#types as ::cecs::systems::SystemParam<#lifetime>>::resources_const(set);)*
+            }
+
+            fn is_non_send() -> bool {
+                false #(|| <#types as ::cecs::systems::SystemParam<#lifetime>>::is_non_send())*
+            }
+
+            fn conflicts() -> ::std::vec::Vec<::cecs::query::Conflict> {
+                let mut props = ::cecs::query::QueryProperties::default();
+                let mut conflicts = ::std::vec::Vec::new();
+                #(::cecs::systems::add_param_conflicts::<#types>(&mut props, &mut conflicts);)*
+                conflicts
+            }
+        }
+    })
+}
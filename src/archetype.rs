@@ -1,4 +1,4 @@
-use std::{any::TypeId, cell::UnsafeCell, collections::BTreeMap};
+use std::{any::TypeId, cell::UnsafeCell, collections::BTreeMap, sync::Arc};
 
 // TODO: use dense storage instead of the Vec because of archetypes
 use crate::{entity_id::EntityId, hash_ty, Component, RowIndex, TypeHash};
@@ -9,6 +9,8 @@ pub struct ArchetypeStorage {
     pub(crate) rows: u32,
     pub(crate) entities: Vec<EntityId>,
     pub(crate) components: BTreeMap<TypeId, UnsafeCell<ErasedTable>>,
+    /// Number of consecutive ticks this archetype has been empty for
+    pub(crate) empty_ticks: u32,
 }
 
 unsafe impl Send for ArchetypeStorage {}
@@ -21,6 +23,7 @@ impl Clone for ArchetypeStorage {
             ty: self.ty,
             rows: self.rows,
             entities: self.entities.clone(),
+            empty_ticks: self.empty_ticks,
             components: self
                 .components
                 .iter()
@@ -67,6 +70,7 @@ impl ArchetypeStorage {
             rows: 0,
             entities: Vec::default(),
             components,
+            empty_ticks: 0,
         }
     }
 
@@ -134,7 +138,7 @@ impl ArchetypeStorage {
                 .get_mut(&TypeId::of::<T>())
                 .expect("set_component called on bad archetype")
                 .get_mut()
-                .as_inner_mut();
+                .as_unique_mut();
             let row_index = row_index as usize;
             assert!(row_index <= v.len());
             if row_index == v.len() {
@@ -188,6 +192,23 @@ impl ArchetypeStorage {
                     .map(|(id, col)| (*id, (unsafe { &*col.get() }.clone_empty)()))
                     .map(|(id, col)| (id, UnsafeCell::new(col))),
             ),
+            empty_ticks: 0,
+        }
+    }
+
+    /// Copy sharing the columns, which are copied when either archetype mutates them
+    #[cfg(feature = "clone")]
+    pub(crate) fn share(&self) -> Self {
+        Self {
+            ty: self.ty,
+            rows: self.rows,
+            entities: self.entities.clone(),
+            empty_ticks: self.empty_ticks,
+            components: self
+                .components
+                .iter()
+                .map(|(ty, col)| unsafe { (*ty, UnsafeCell::new((*col.get()).share())) })
+                .collect(),
         }
     }
 
@@ -197,22 +218,39 @@ impl ArchetypeStorage {
             .and_then(|columns| unsafe { (*columns.get()).as_inner().get(row as usize) })
     }
 
+    /// The column must be unique, see [[ErasedTable::make_unique]]
     pub fn get_component_mut<T: 'static>(&self, row: RowIndex) -> Option<&mut T> {
         self.components
             .get(&TypeId::of::<T>())
             .and_then(|columns| unsafe { (*columns.get()).as_inner_mut().get_mut(row as usize) })
     }
+
+    /// Copies only the column of `T` if it's shared with a forked World
+    pub fn get_component_unique_mut<T: 'static>(&mut self, row: RowIndex) -> Option<&mut T> {
+        self.components
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|columns| unsafe { columns.get_mut().as_unique_mut().get_mut(row as usize) })
+    }
 }
 
 /// Type erased Vec
 pub(crate) struct ErasedTable {
-    ty_name: &'static str,
+    pub(crate) ty_name: &'static str,
+    /// size of a single item in bytes
+    pub(crate) item_size: usize,
+    /// `Arc<Vec<T>>`, shared by forked Worlds until either of them mutates it
     inner: *mut u8,
+    capacity: fn(&ErasedTable) -> usize,
     finalize: fn(&mut ErasedTable),
     /// remove is always swap_remove
     remove: fn(RowIndex, &mut ErasedTable),
     #[cfg(feature = "clone")]
     clone: fn(&ErasedTable) -> ErasedTable,
+    #[cfg(feature = "clone")]
+    share: fn(&ErasedTable) -> ErasedTable,
+    /// Copy the inner table if it's shared
+    #[cfg(feature = "clone")]
+    make_unique: fn(&mut ErasedTable),
     clone_empty: fn() -> ErasedTable,
     /// src, dst
     ///
@@ -249,17 +287,25 @@ impl std::fmt::Debug for ErasedTable {
 
 impl ErasedTable {
     pub fn new<T: crate::Component>(table: Vec<T>) -> Self {
+        Self::from_arc(Arc::new(table))
+    }
+
+    fn from_arc<T: crate::Component>(table: Arc<Vec<T>>) -> Self {
         Self {
             ty_name: std::any::type_name::<T>(),
-            inner: Box::into_raw(Box::new(table)).cast(),
+            item_size: std::mem::size_of::<T>(),
+            inner: Arc::into_raw(table) as *mut u8,
+            capacity: |erased_table: &ErasedTable| unsafe {
+                erased_table.as_inner::<T>().capacity()
+            },
             finalize: |erased_table: &mut ErasedTable| {
                 // drop the inner table
                 unsafe {
-                    let _ = Box::from_raw(erased_table.inner.cast::<Vec<T>>());
+                    let _ = Arc::from_raw(erased_table.inner.cast::<Vec<T>>());
                 }
             },
             remove: |entity_id, erased_table: &mut ErasedTable| unsafe {
-                let v = erased_table.as_inner_mut::<T>();
+                let v = erased_table.as_unique_mut::<T>();
                 v.swap_remove(entity_id as usize);
             },
             #[cfg(feature = "clone")]
@@ -268,10 +314,24 @@ impl ErasedTable {
                 let res: Vec<T> = inner.clone();
                 ErasedTable::new(res)
             },
+            #[cfg(feature = "clone")]
+            share: |table: &ErasedTable| unsafe {
+                let inner = table.inner.cast::<Vec<T>>();
+                Arc::increment_strong_count(inner);
+                ErasedTable::from_arc(Arc::from_raw(inner))
+            },
+            #[cfg(feature = "clone")]
+            make_unique: |table: &mut ErasedTable| unsafe {
+                // if cloning panics the table still owns the original
+                let mut inner =
+                    std::mem::ManuallyDrop::new(Arc::from_raw(table.inner.cast::<Vec<T>>()));
+                Arc::make_mut(&mut inner);
+                table.inner = Arc::as_ptr(&inner) as *mut u8;
+            },
             clone_empty: || ErasedTable::new::<T>(Vec::default()),
             move_row: |src, dst, index| unsafe {
-                let src = src.as_inner_mut::<T>();
-                let dst = dst.as_inner_mut::<T>();
+                let src = src.as_unique_mut::<T>();
+                let dst = dst.as_unique_mut::<T>();
                 let src = src.swap_remove(index as usize);
                 dst.push(src);
             },
@@ -285,12 +345,39 @@ impl ErasedTable {
     }
 
     /// # SAFETY
-    /// Must be called with the same type as `new`
+    /// Must be called with the same type as `new`, and the table must be unique, see
+    /// [[ErasedTable::make_unique]]
     pub unsafe fn as_inner_mut<T>(&mut self) -> &mut Vec<T> {
         &mut *self.inner.cast()
     }
 
+    /// Copies the table if it's shared with a forked World
+    ///
+    /// # SAFETY
+    /// Must be called with the same type as `new`
+    pub unsafe fn as_unique_mut<T>(&mut self) -> &mut Vec<T> {
+        #[cfg(feature = "clone")]
+        self.make_unique();
+        self.as_inner_mut()
+    }
+
+    /// Copies the table if it's shared with a forked World
+    #[cfg(feature = "clone")]
+    pub fn make_unique(&mut self) {
+        (self.make_unique)(self);
+    }
+
+    #[cfg(feature = "clone")]
+    pub fn share(&self) -> Self {
+        (self.share)(self)
+    }
+
     pub fn remove(&mut self, id: RowIndex) {
         (self.remove)(id, self);
     }
+
+    /// Number of items the table can hold without reallocating
+    pub fn capacity(&self) -> usize {
+        (self.capacity)(self)
+    }
 }
@@ -1,10 +1,77 @@
+use std::any::TypeId;
+
 use crate::{archetype::ArchetypeStorage, hash_ty, Component, RowIndex, TypeHash, WorldResult};
 
+/// Derive `Bundle` for structs with named fields
+///
+/// ```
+/// use cecs::prelude::*;
+///
+/// #[derive(Bundle)]
+/// struct Transform {
+///     pos: [f32; 2],
+///     rot: f32,
+/// }
+///
+/// #[derive(Bundle)]
+/// struct Bot {
+///     name: String,
+///     #[bundle]
+///     transform: Transform,
+/// }
+///
+/// let mut world = World::new(4);
+/// let id = world.insert_entity().unwrap();
+/// world
+///     .set_bundle(
+///         id,
+///         Bot {
+///             name: "bot".to_string(),
+///             transform: Transform {
+///                 pos: [1.0, 2.0],
+///                 rot: 0.5,
+///             },
+///         },
+///     )
+///     .unwrap();
+/// assert_eq!(world.get_component::<f32>(id), Some(&0.5));
+/// ```
+///
+/// Every component type may only appear once in a bundle:
+///
+/// ```compile_fail
+/// use cecs::prelude::*;
+///
+/// #[derive(Bundle)]
+/// struct Span {
+///     start: u32,
+///     end: u32,
+/// }
+/// ```
+pub use cecs_macros::Bundle;
+
 pub trait Bundle {
     fn compute_hash(base: TypeHash) -> TypeHash;
     fn can_insert(&self, archetype: &ArchetypeStorage) -> bool;
     fn insert(self, archetype: &mut ArchetypeStorage, index: RowIndex) -> WorldResult<()>;
     fn extend(archetype: &ArchetypeStorage) -> ArchetypeStorage;
+
+    /// Push the [TypeId] of every component in the bundle
+    fn component_ids(ids: &mut Vec<TypeId>) {
+        let archetype = Self::extend(&ArchetypeStorage::empty());
+        ids.extend(
+            archetype
+                .components
+                .keys()
+                .filter(|ty| **ty != TypeId::of::<()>()),
+        );
+    }
+}
+
+/// Used by the code generated by `#[derive(Bundle)]`
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::{archetype::ArchetypeStorage, TypeHash};
 }
 
 macro_rules! impl_tuple {
@@ -32,6 +99,10 @@ macro_rules! impl_tuple {
                 )*
                 result
             }
+
+            fn component_ids(ids: &mut Vec<TypeId>) {
+                $(ids.push(TypeId::of::<$ty>());)*
+            }
         }
     };
 }
@@ -0,0 +1,95 @@
+//! Ring buffer of World snapshots, see [[World::checkpoint]] and [[World::rollback]]
+use std::collections::VecDeque;
+
+use crate::{
+    archetype::ArchetypeStorage, handle_table::EntityIndex, resources::ResourceStorage, Archetypes,
+    World,
+};
+
+pub const DEFAULT_CHECKPOINT_CAPACITY: usize = 8;
+
+struct Snapshot {
+    entity_ids: EntityIndex,
+    archetypes: Archetypes,
+    resources: ResourceStorage,
+}
+
+pub(crate) struct Checkpoints {
+    capacity: usize,
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl Default for Checkpoints {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CHECKPOINT_CAPACITY,
+            snapshots: VecDeque::new(),
+        }
+    }
+}
+
+/// Checkpoints are not cloned
+impl Clone for Checkpoints {
+    fn clone(&self) -> Self {
+        Self {
+            capacity: self.capacity,
+            snapshots: VecDeque::new(),
+        }
+    }
+}
+
+impl Checkpoints {
+    pub(crate) fn push(&mut self, world: &World) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.snapshots.len() >= self.capacity {
+            self.snapshots.pop_front();
+        }
+        // the snapshot shares the columns and resources the World does not mutate afterwards
+        let (entity_ids, archetypes) = world.copy_entities(ArchetypeStorage::share);
+        self.snapshots.push_back(Snapshot {
+            entity_ids,
+            archetypes,
+            // non-send resources are not shared
+            resources: world.resources.share(),
+        });
+    }
+
+    /// Remove the `n` most recent checkpoints, and restore the oldest of them
+    pub(crate) fn rollback(&mut self, world: &mut World, n: usize) -> bool {
+        if n == 0 || n > self.snapshots.len() {
+            return false;
+        }
+        self.snapshots.truncate(self.snapshots.len() - n + 1);
+        let snapshot = self.snapshots.pop_back().unwrap();
+        // the archetypes are boxed, so the pointers in `entity_ids` stay valid
+        world.entity_ids = snapshot.entity_ids;
+        world.archetypes = snapshot.archetypes;
+        world.resources.resources = snapshot.resources.resources;
+        world.indexes.mark_all_dirty();
+        // pending commands were issued against the discarded state
+        for commands in world.commands.iter_mut() {
+            commands.get_mut().clear();
+        }
+        for commands in world.resource_commands.iter_mut() {
+            commands.get_mut().clear();
+        }
+        true
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.snapshots.len() > capacity {
+            self.snapshots.pop_front();
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
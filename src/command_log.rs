@@ -0,0 +1,314 @@
+//! Recording of applied commands, and their replay
+//!
+//! Only the components and resources registered via [[World::register_logged_component]] and
+//! [[World::register_logged_resource]] are recorded. Values are encoded with bincode and keyed by
+//! their type name, so a log can only be replayed by a build with the same type names.
+//!
+//! Components set by a command are recorded with the value they have after the command was
+//! applied, so `update` and `insert_if_absent` commands replay as plain inserts.
+//!
+//! # Limitations
+//!
+//! Only changes made by [[crate::commands::Commands]] are recorded. Replaying a log does not
+//! reproduce changes made by
+//!
+//! - systems writing components via `Query<&mut T>` or resources via `ResMut<T>`
+//! - exclusive systems, or any other code, mutating the World directly
+//! - closures added via [[crate::commands::Commands::add]], only the commands they issue are
+//!   recorded
+//!
+//! Games recording a log should route the state they want to replay through commands.
+use std::{any::TypeId, collections::HashMap};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{entity_id::EntityId, Component, World, WorldError};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoggedCommand {
+    Spawn(EntityId),
+    Delete(EntityId),
+    SetComponent {
+        entity: EntityId,
+        ty: String,
+        value: Vec<u8>,
+    },
+    RemoveComponent {
+        entity: EntityId,
+        ty: String,
+    },
+    InsertResource {
+        ty: String,
+        value: Vec<u8>,
+    },
+    RemoveResource {
+        ty: String,
+    },
+}
+
+/// Commands applied during a single tick, in the order they were applied
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TickLog {
+    pub commands: Vec<LoggedCommand>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandLog {
+    pub ticks: Vec<TickLog>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("Type {0} was not registered for logging")]
+    UnregisteredType(String),
+    #[error("Failed to decode {ty}: {error}")]
+    Decode { ty: String, error: bincode::Error },
+    #[error("Spawned entity {actual}, but the log expected {expected}")]
+    EntityIdMismatch {
+        expected: EntityId,
+        actual: EntityId,
+    },
+    #[error(transparent)]
+    World(#[from] WorldError),
+}
+
+#[derive(Clone, Copy)]
+struct ComponentEntry {
+    name: &'static str,
+    encode: fn(&World, EntityId) -> Option<Vec<u8>>,
+    set: fn(&mut World, EntityId, &[u8]) -> Result<(), ReplayError>,
+    remove: fn(&mut World, EntityId) -> Result<(), WorldError>,
+}
+
+#[derive(Clone, Copy)]
+struct ResourceEntry {
+    name: &'static str,
+    encode: fn(&World) -> Option<Vec<u8>>,
+    insert: fn(&mut World, &[u8]) -> Result<(), ReplayError>,
+    remove: fn(&mut World),
+}
+
+/// Records the commands applied to the World, see the [module docs](self) for what is not recorded
+#[derive(Default)]
+pub(crate) struct CommandLogger {
+    components: HashMap<TypeId, ComponentEntry>,
+    component_names: HashMap<&'static str, TypeId>,
+    resources: HashMap<TypeId, ResourceEntry>,
+    resource_names: HashMap<&'static str, TypeId>,
+    /// `Some` while recording
+    log: Option<CommandLog>,
+}
+
+/// Clones the registered types, but not the log being recorded
+#[cfg(feature = "clone")]
+impl Clone for CommandLogger {
+    fn clone(&self) -> Self {
+        Self {
+            components: self.components.clone(),
+            component_names: self.component_names.clone(),
+            resources: self.resources.clone(),
+            resource_names: self.resource_names.clone(),
+            log: None,
+        }
+    }
+}
+
+fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+    bincode::serialize(value).unwrap_or_else(|err| {
+        panic!(
+            "Failed to encode {} for the command log: {}",
+            std::any::type_name::<T>(),
+            err
+        )
+    })
+}
+
+fn decode<T: DeserializeOwned>(value: &[u8]) -> Result<T, ReplayError> {
+    bincode::deserialize(value).map_err(|error| ReplayError::Decode {
+        ty: std::any::type_name::<T>().to_string(),
+        error,
+    })
+}
+
+impl CommandLogger {
+    pub(crate) fn register_component<T: Component + Serialize + DeserializeOwned>(&mut self) {
+        let name = std::any::type_name::<T>();
+        self.components.insert(
+            TypeId::of::<T>(),
+            ComponentEntry {
+                name,
+                encode: |world, id| world.get_component::<T>(id).map(encode),
+                set: |world, id, value| {
+                    world.set_component(id, decode::<T>(value)?)?;
+                    Ok(())
+                },
+                remove: |world, id| world.remove_component::<T>(id),
+            },
+        );
+        self.component_names.insert(name, TypeId::of::<T>());
+    }
+
+    pub(crate) fn register_resource<T: Component + Serialize + DeserializeOwned>(&mut self) {
+        let name = std::any::type_name::<T>();
+        self.resources.insert(
+            TypeId::of::<T>(),
+            ResourceEntry {
+                name,
+                encode: |world| world.get_resource::<T>().map(encode),
+                insert: |world, value| {
+                    world.insert_resource(decode::<T>(value)?);
+                    Ok(())
+                },
+                remove: |world| {
+                    world.remove_resource::<T>();
+                },
+            },
+        );
+        self.resource_names.insert(name, TypeId::of::<T>());
+    }
+
+    pub(crate) fn start(&mut self) {
+        self.log = Some(CommandLog::default());
+    }
+
+    pub(crate) fn take(&mut self) -> Option<CommandLog> {
+        self.log.take()
+    }
+
+    pub(crate) fn log(&self) -> Option<&CommandLog> {
+        self.log.as_ref()
+    }
+
+    pub(crate) fn is_recording(&self) -> bool {
+        self.log.is_some()
+    }
+
+    pub(crate) fn begin_tick(&mut self) {
+        if let Some(log) = self.log.as_mut() {
+            log.ticks.push(TickLog::default());
+        }
+    }
+
+    fn push(&mut self, cmd: LoggedCommand) {
+        if let Some(log) = self.log.as_mut() {
+            // commands applied before the first tick
+            if log.ticks.is_empty() {
+                log.ticks.push(TickLog::default());
+            }
+            log.ticks.last_mut().unwrap().commands.push(cmd);
+        }
+    }
+
+    fn component(&self, ty: &str) -> Result<ComponentEntry, ReplayError> {
+        self.component_names
+            .get(ty)
+            .map(|id| self.components[id])
+            .ok_or_else(|| ReplayError::UnregisteredType(ty.to_string()))
+    }
+
+    fn resource(&self, ty: &str) -> Result<ResourceEntry, ReplayError> {
+        self.resource_names
+            .get(ty)
+            .map(|id| self.resources[id])
+            .ok_or_else(|| ReplayError::UnregisteredType(ty.to_string()))
+    }
+}
+
+pub(crate) fn record_spawn(world: &mut World, id: EntityId) {
+    world.command_log.push(LoggedCommand::Spawn(id));
+}
+
+pub(crate) fn record_delete(world: &mut World, id: EntityId) {
+    world.command_log.push(LoggedCommand::Delete(id));
+}
+
+pub(crate) fn record_set_component(world: &mut World, id: EntityId, ty: TypeId) {
+    if !world.command_log.is_recording() {
+        return;
+    }
+    let Some(entry) = world.command_log.components.get(&ty).copied() else {
+        return;
+    };
+    if let Some(value) = (entry.encode)(world, id) {
+        world.command_log.push(LoggedCommand::SetComponent {
+            entity: id,
+            ty: entry.name.to_string(),
+            value,
+        });
+    }
+}
+
+/// Records every registered component of the bundle
+pub(crate) fn record_set_bundle<T: crate::bundle::Bundle>(world: &mut World, id: EntityId) {
+    if !world.command_log.is_recording() {
+        return;
+    }
+    let mut components = Vec::new();
+    T::component_ids(&mut components);
+    for ty in components {
+        record_set_component(world, id, ty);
+    }
+}
+
+pub(crate) fn record_remove_component(world: &mut World, id: EntityId, ty: TypeId) {
+    if let Some(entry) = world.command_log.components.get(&ty) {
+        let ty = entry.name.to_string();
+        world
+            .command_log
+            .push(LoggedCommand::RemoveComponent { entity: id, ty });
+    }
+}
+
+pub(crate) fn record_insert_resource(world: &mut World, ty: TypeId) {
+    if !world.command_log.is_recording() {
+        return;
+    }
+    let Some(entry) = world.command_log.resources.get(&ty).copied() else {
+        return;
+    };
+    if let Some(value) = (entry.encode)(world) {
+        world.command_log.push(LoggedCommand::InsertResource {
+            ty: entry.name.to_string(),
+            value,
+        });
+    }
+}
+
+pub(crate) fn record_remove_resource(world: &mut World, ty: TypeId) {
+    if let Some(entry) = world.command_log.resources.get(&ty) {
+        let ty = entry.name.to_string();
+        world.command_log.push(LoggedCommand::RemoveResource { ty });
+    }
+}
+
+pub(crate) fn replay(world: &mut World, cmd: &LoggedCommand) -> Result<(), ReplayError> {
+    match cmd {
+        LoggedCommand::Spawn(expected) => {
+            let actual = world.insert_entity()?;
+            if actual != *expected {
+                return Err(ReplayError::EntityIdMismatch {
+                    expected: *expected,
+                    actual,
+                });
+            }
+        }
+        LoggedCommand::Delete(id) => world.delete_entity(*id)?,
+        LoggedCommand::SetComponent { entity, ty, value } => {
+            let entry = world.command_log.component(ty)?;
+            (entry.set)(world, *entity, value)?;
+        }
+        LoggedCommand::RemoveComponent { entity, ty } => {
+            let entry = world.command_log.component(ty)?;
+            (entry.remove)(world, *entity)?;
+        }
+        LoggedCommand::InsertResource { ty, value } => {
+            let entry = world.command_log.resource(ty)?;
+            (entry.insert)(world, value)?;
+        }
+        LoggedCommand::RemoveResource { ty } => {
+            let entry = world.command_log.resource(ty)?;
+            (entry.remove)(world);
+        }
+    }
+    Ok(())
+}
@@ -1,8 +1,8 @@
 use std::ptr::NonNull;
 
 use crate::{
-    entity_id::EntityId, prelude::Bundle, query::WorldQuery, CommandBuffer, Component, World,
-    WorldError,
+    entity_id::EntityId, prelude::Bundle, systems::SystemParam, CommandBuffer, Component,
+    ParallelComponent, World, WorldError,
 };
 
 pub struct Commands<'a> {
@@ -13,24 +13,24 @@ pub struct Commands<'a> {
 unsafe impl<'a> Send for Commands<'a> {}
 unsafe impl<'a> Sync for Commands<'a> {}
 
-impl<'a> WorldQuery<'a> for Commands<'a> {
-    fn new(w: &'a World, commands_index: usize) -> Self {
+unsafe impl<'a> SystemParam<'a> for Commands<'a> {
+    fn new(w: &'a World, commands_index: usize, _ticks: crate::systems::SystemTicks) -> Self {
         Self::new(w, commands_index)
     }
 
-    fn components_mut(_set: &mut std::collections::HashSet<std::any::TypeId>) {
+    fn components_mut(_set: &mut std::collections::HashSet<crate::query::TypeDesc>) {
         // noop
     }
 
-    fn resources_mut(_set: &mut std::collections::HashSet<std::any::TypeId>) {
+    fn resources_mut(_set: &mut std::collections::HashSet<crate::query::TypeDesc>) {
         // noop
     }
 
-    fn components_const(_set: &mut std::collections::HashSet<std::any::TypeId>) {
+    fn components_const(_set: &mut std::collections::HashSet<crate::query::TypeDesc>) {
         // noop
     }
 
-    fn resources_const(_set: &mut std::collections::HashSet<std::any::TypeId>) {
+    fn resources_const(_set: &mut std::collections::HashSet<crate::query::TypeDesc>) {
         // noop
     }
 }
@@ -75,6 +75,20 @@ impl<'a> Commands<'a> {
         }
     }
 
+    /// Run `f` on the World when the commands are applied
+    ///
+    /// Runs in order with the entity commands of this system. Resource commands are applied after
+    /// all entity commands.
+    pub fn add(&mut self, f: impl FnOnce(&mut World) + ParallelComponent + 'static) {
+        unsafe {
+            let cmd = &mut *self.entity_cmd.get();
+            cmd.push(EntityCommands {
+                action: EntityAction::Custom(Box::new(f)),
+                payload: Vec::default(),
+            });
+        }
+    }
+
     pub fn insert_resource<T: Component>(&mut self, resource: T) {
         unsafe {
             let cmd = &mut *self.resource_cmd.get();
@@ -93,7 +107,7 @@ impl<'a> Commands<'a> {
 }
 
 pub struct EntityCommands {
-    /// if the action is delete, then `payload` is ignored
+    /// if the action is delete or custom, then `payload` is ignored
     action: EntityAction,
     payload: Vec<ErasedComponentCommand>,
 }
@@ -102,14 +116,29 @@ enum EntityAction {
     Fetch(EntityId),
     Insert,
     Delete(EntityId),
+    Custom(Box<dyn FnOnce(&mut World)>),
 }
 
 impl EntityCommands {
     pub(crate) fn apply(self, world: &mut World) -> Result<(), WorldError> {
         let id = match self.action {
             EntityAction::Fetch(id) => id,
-            EntityAction::Insert => world.insert_entity()?,
-            EntityAction::Delete(id) => return world.delete_entity(id),
+            EntityAction::Insert => {
+                let id = world.insert_entity()?;
+                #[cfg(feature = "serde")]
+                crate::command_log::record_spawn(world, id);
+                id
+            }
+            EntityAction::Delete(id) => {
+                world.delete_entity(id)?;
+                #[cfg(feature = "serde")]
+                crate::command_log::record_delete(world, id);
+                return Ok(());
+            }
+            EntityAction::Custom(f) => {
+                f(world);
+                return Ok(());
+            }
         };
         if !world.is_id_valid(id) {
             return Err(WorldError::EntityNotFound);
@@ -127,6 +156,28 @@ impl EntityCommands {
         self
     }
 
+    /// Insert the component if the entity doesn't have one yet
+    pub fn insert_if_absent<T: Component>(&mut self, component: T) -> &mut Self {
+        self.payload.push(ErasedComponentCommand::from_component(
+            ComponentCommand::InsertIfAbsent(component),
+        ));
+        self
+    }
+
+    /// Modify the component in place when the commands are applied
+    ///
+    /// Applying fails with [[WorldError::ComponentNotFound]] if the entity doesn't have the
+    /// component by then
+    pub fn update<T: Component>(
+        &mut self,
+        f: impl FnOnce(&mut T) + ParallelComponent + 'static,
+    ) -> &mut Self {
+        self.payload.push(ErasedComponentCommand::from_component(
+            ComponentCommand::Update(Box::new(f)),
+        ));
+        self
+    }
+
     pub fn insert_bundle<T: Bundle>(&mut self, component: T) -> &mut Self {
         self.payload
             .push(ErasedComponentCommand::from_bundle(BundleCommand::Insert(
@@ -211,6 +262,8 @@ impl<T: Bundle> BundleCommand<T> {
         match self {
             BundleCommand::Insert(bundle) => {
                 world.set_bundle(entity_id, bundle)?;
+                #[cfg(feature = "serde")]
+                crate::command_log::record_set_bundle::<T>(world, entity_id);
             }
         }
         Ok(())
@@ -219,6 +272,8 @@ impl<T: Bundle> BundleCommand<T> {
 
 pub(crate) enum ComponentCommand<T> {
     Insert(T),
+    InsertIfAbsent(T),
+    Update(Box<dyn FnOnce(&mut T)>),
     Delete,
 }
 
@@ -228,10 +283,38 @@ impl<T: Component> ComponentCommand<T> {
             ComponentCommand::Insert(comp) => {
                 world.set_component(entity_id, comp)?;
             }
+            ComponentCommand::InsertIfAbsent(comp) => {
+                if world.get_component::<T>(entity_id).is_some() {
+                    return Ok(());
+                }
+                world.set_component(entity_id, comp)?;
+            }
+            ComponentCommand::Update(f) => {
+                let (mut archetype, index) = world
+                    .entity_ids
+                    .read(entity_id)
+                    .map_err(|_| WorldError::EntityNotFound)?;
+                let comp = unsafe { archetype.as_mut() }
+                    .get_component_unique_mut::<T>(index)
+                    .ok_or(WorldError::ComponentNotFound)?;
+                world
+                    .indexes
+                    .mark_entity(&[std::any::TypeId::of::<T>()], entity_id);
+                f(comp);
+            }
             ComponentCommand::Delete => {
                 world.remove_component::<T>(entity_id)?;
+                #[cfg(feature = "serde")]
+                crate::command_log::record_remove_component(
+                    world,
+                    entity_id,
+                    std::any::TypeId::of::<T>(),
+                );
+                return Ok(());
             }
         }
+        #[cfg(feature = "serde")]
+        crate::command_log::record_set_component(world, entity_id, std::any::TypeId::of::<T>());
         Ok(())
     }
 }
@@ -247,7 +330,9 @@ unsafe impl Sync for ErasedResourceCommand {}
 
 impl Drop for ErasedResourceCommand {
     fn drop(&mut self) {
-        (self.drop)(NonNull::new(self.inner).unwrap());
+        if !self.inner.is_null() {
+            (self.drop)(NonNull::new(self.inner).unwrap());
+        }
     }
 }
 
@@ -269,8 +354,10 @@ impl ErasedResourceCommand {
         }
     }
 
-    pub fn apply(self, world: &mut World) -> Result<(), WorldError> {
-        (self.apply)(NonNull::new(self.inner).unwrap(), world)
+    pub fn apply(mut self, world: &mut World) -> Result<(), WorldError> {
+        let ptr = NonNull::new(self.inner).unwrap();
+        self.inner = std::ptr::null_mut();
+        (self.apply)(ptr, world)
     }
 }
 
@@ -284,9 +371,13 @@ impl<T: Component> ResourceCommand<T> {
         match self {
             ResourceCommand::Insert(comp) => {
                 world.insert_resource::<T>(comp);
+                #[cfg(feature = "serde")]
+                crate::command_log::record_insert_resource(world, std::any::TypeId::of::<T>());
             }
             ResourceCommand::Delete => {
                 world.remove_resource::<T>();
+                #[cfg(feature = "serde")]
+                crate::command_log::record_remove_resource(world, std::any::TypeId::of::<T>());
             }
         }
         Ok(())
@@ -321,6 +412,25 @@ mod tests {
         assert_eq!(cnt, 3);
     }
 
+    #[test]
+    fn applied_resource_command_is_dropped_once_test() {
+        let mut world = World::new(100);
+        let value = std::sync::Arc::new(42);
+
+        world
+            .ensure_commands()
+            .insert_resource(std::sync::Arc::clone(&value));
+        world.apply_commands().unwrap();
+        // the command moved its payload into the World, it must not drop it again
+        assert_eq!(std::sync::Arc::strong_count(&value), 2);
+
+        world
+            .ensure_commands()
+            .remove_resource::<std::sync::Arc<i32>>();
+        world.apply_commands().unwrap();
+        assert_eq!(std::sync::Arc::strong_count(&value), 1);
+    }
+
     #[test]
     fn can_remove_component_test() {
         let mut world = World::new(100);
@@ -363,4 +473,64 @@ mod tests {
         let c = Query::<&()>::new(&world).fetch(id);
         assert!(c.is_none());
     }
+
+    #[test]
+    fn update_and_custom_commands_test() {
+        let mut world = World::new(100);
+
+        let id = world.insert_entity().unwrap();
+        world.set_component(id, 1i32).unwrap();
+
+        {
+            let mut cmd = world.ensure_commands();
+            cmd.entity(id)
+                .update::<i32>(|i| *i += 1)
+                .insert_if_absent(10i32)
+                .insert_if_absent(20u32)
+                .update::<u32>(|u| *u *= 2);
+            cmd.add(move |world| {
+                let i = *world.get_component::<i32>(id).unwrap();
+                world.set_component(id, i as u64 * 100).unwrap();
+            });
+            cmd.entity(id).update::<u64>(|u| *u += 1);
+        }
+        world.apply_commands().unwrap();
+
+        assert_eq!(world.get_component::<i32>(id), Some(&2));
+        assert_eq!(world.get_component::<u32>(id), Some(&40));
+        assert_eq!(world.get_component::<u64>(id), Some(&201));
+
+        world
+            .ensure_commands()
+            .entity(id)
+            .update::<String>(|s| s.push('!'));
+        assert!(matches!(
+            world.apply_commands(),
+            Err(WorldError::ComponentNotFound)
+        ));
+    }
+
+    #[test]
+    fn commands_issued_by_commands_are_applied_test() {
+        let mut world = World::new(100);
+
+        world.ensure_commands().add(|world| {
+            let mut cmd = world.ensure_commands();
+            cmd.spawn().insert(1i32);
+            cmd.insert_resource(2u32);
+            cmd.add(|world| {
+                world.ensure_commands().spawn().insert(3i32);
+            });
+        });
+        world.apply_commands().unwrap();
+
+        let mut values = Query::<&i32>::new(&world)
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        values.sort_unstable();
+        assert_eq!(values, [1, 3]);
+        assert_eq!(world.get_resource::<u32>(), Some(&2));
+        assert!(!world.has_pending_commands());
+    }
 }
@@ -137,6 +137,30 @@ impl HandleTable {
         self.len() == 0
     }
 
+    pub fn capacity(&self) -> usize {
+        self.cap as usize
+    }
+
+    /// Walks the free list and returns its length
+    pub fn free_list_len(&self) -> usize {
+        self.free_list().count()
+    }
+
+    /// The ids that will be allocated next, in order
+    pub(crate) fn free_list(&self) -> impl Iterator<Item = EntityId> + '_ {
+        let entries = self.entries();
+        let mut i = self.free_list;
+        std::iter::from_fn(move || {
+            if i == SENTINEL {
+                return None;
+            }
+            let entry = entries[i as usize];
+            let id = EntityId::new(i, entry.gen);
+            i = entry.data;
+            Some(id)
+        })
+    }
+
     pub fn alloc(&mut self) -> Result<EntityId, HandleTableError> {
         // pop element off the free list
         //
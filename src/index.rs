@@ -0,0 +1,386 @@
+//! Secondary indexes, mapping keys computed from component values to entities
+//!
+//! Indexes are updated lazily, when they're accessed after their component may have changed:
+//! entities whose component was inserted or removed, or that were deleted, are re-keyed, and so
+//! are the archetypes a query handed out mutably.
+use std::{
+    any::{Any, TypeId},
+    cell::UnsafeCell,
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    archetype::ArchetypeStorage,
+    entity_id::EntityId,
+    query::{ArchQuery, QueryFragment, TypeDesc},
+    systems::{SystemParam, SystemTicks},
+    Component, TypeHash, World,
+};
+
+pub trait IndexKey: Hash + Eq + Clone + Send + Sync + 'static {}
+impl<T: Hash + Eq + Clone + Send + Sync + 'static> IndexKey for T {}
+
+pub(crate) trait ErasedIndex: Send + Sync {
+    fn component(&self) -> TypeId;
+    #[cfg(feature = "clone")]
+    fn mark_all_dirty(&self);
+    fn mark_entity(&self, id: EntityId);
+    fn mark_archetype(&self, ty: TypeHash);
+    /// The entity left the archetype `from`
+    fn entity_moved(&self, from: TypeHash, id: EntityId);
+    fn as_any(&self) -> &dyn Any;
+    #[cfg(feature = "clone")]
+    fn clone_empty(&self) -> Box<dyn ErasedIndex>;
+}
+
+/// Entities whose key may have changed since the index was last updated
+#[derive(Default)]
+struct Stale {
+    all: bool,
+    entities: HashSet<EntityId>,
+    archetypes: HashSet<TypeHash>,
+}
+
+pub(crate) struct IndexStorage<C, K> {
+    key: fn(&C) -> K,
+    unique: bool,
+    dirty: AtomicBool,
+    stale: Mutex<Stale>,
+    entities: UnsafeCell<HashMap<K, Vec<EntityId>>>,
+    keys: UnsafeCell<HashMap<EntityId, K>>,
+}
+
+// # SAFETY
+// `entities` and `keys` are only mutated while `stale` is locked and the index is dirty. Readers
+// hold an `Index` param, which conflicts with systems mutating `C`, so the index can not be
+// marked dirty while it's being read.
+unsafe impl<C, K: Send> Send for IndexStorage<C, K> {}
+unsafe impl<C, K: Sync> Sync for IndexStorage<C, K> {}
+
+impl<C: Component, K: IndexKey> IndexStorage<C, K> {
+    pub(crate) fn new(key: fn(&C) -> K, unique: bool) -> Self {
+        Self {
+            key,
+            unique,
+            dirty: AtomicBool::new(true),
+            stale: Mutex::new(Stale {
+                all: true,
+                ..Default::default()
+            }),
+            entities: UnsafeCell::new(HashMap::new()),
+            keys: UnsafeCell::new(HashMap::new()),
+        }
+    }
+
+    fn mark(&self, f: impl FnOnce(&mut Stale)) {
+        f(&mut self.stale.lock().unwrap());
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    fn ensure_fresh(&self, world: &World) {
+        if !self.dirty.load(Ordering::Acquire) {
+            return;
+        }
+        let mut stale = self.stale.lock().unwrap();
+        if !self.dirty.load(Ordering::Acquire) {
+            return;
+        }
+        let stale = std::mem::take(&mut *stale);
+        // keys are only unique once every stale entity is updated
+        let mut updated = Vec::new();
+        if stale.all {
+            #[cfg(feature = "tracing")]
+            tracing::trace!(
+                component = std::any::type_name::<C>(),
+                key = std::any::type_name::<K>(),
+                "Rebuild index"
+            );
+            unsafe {
+                (*self.entities.get()).clear();
+                (*self.keys.get()).clear();
+            }
+            for archetype in world.archetypes.values() {
+                self.update_archetype(archetype, &mut updated);
+            }
+        } else {
+            for ty in stale.archetypes {
+                if let Some(archetype) = world.archetypes.get(&ty) {
+                    self.update_archetype(archetype, &mut updated);
+                }
+            }
+            for id in stale.entities {
+                let c = world
+                    .entity_ids
+                    .read(id)
+                    .ok()
+                    .and_then(|(archetype, row)| unsafe { archetype.as_ref().get_component(row) });
+                self.update(id, c.map(self.key), &mut updated);
+            }
+        }
+        let entities = unsafe { &*self.entities.get() };
+        debug_assert!(
+            !self.unique
+                || updated
+                    .iter()
+                    .all(|key| entities.get(key).is_none_or(|ids| ids.len() == 1)),
+            "Unique index on {} has multiple entities with the same key",
+            std::any::type_name::<C>()
+        );
+        self.dirty.store(false, Ordering::Release);
+    }
+
+    fn update_archetype(&self, archetype: &ArchetypeStorage, updated: &mut Vec<K>) {
+        if !archetype.contains_column::<C>() {
+            return;
+        }
+        for (id, c) in ArchQuery::<(EntityId, &C)>::iter(archetype) {
+            self.update(id, Some((self.key)(c)), updated);
+        }
+    }
+
+    /// Move the entity to its new key, `None` removes it from the index
+    fn update(&self, id: EntityId, key: Option<K>, updated: &mut Vec<K>) {
+        let entities = unsafe { &mut *self.entities.get() };
+        let keys = unsafe { &mut *self.keys.get() };
+        if keys.get(&id) == key.as_ref() {
+            return;
+        }
+        if let Some(old) = keys.remove(&id) {
+            let ids = entities.get_mut(&old).unwrap();
+            ids.retain(|x| *x != id);
+            if ids.is_empty() {
+                entities.remove(&old);
+            }
+        }
+        if let Some(key) = key {
+            entities.entry(key.clone()).or_default().push(id);
+            if cfg!(debug_assertions) && self.unique {
+                updated.push(key.clone());
+            }
+            keys.insert(id, key);
+        }
+    }
+}
+
+impl<C: Component, K: IndexKey> ErasedIndex for IndexStorage<C, K> {
+    fn component(&self) -> TypeId {
+        TypeId::of::<C>()
+    }
+
+    #[cfg(feature = "clone")]
+    fn mark_all_dirty(&self) {
+        self.mark(|stale| stale.all = true);
+    }
+
+    fn mark_entity(&self, id: EntityId) {
+        self.mark(|stale| {
+            stale.entities.insert(id);
+        });
+    }
+
+    fn mark_archetype(&self, ty: TypeHash) {
+        self.mark(|stale| {
+            stale.archetypes.insert(ty);
+        });
+    }
+
+    fn entity_moved(&self, from: TypeHash, id: EntityId) {
+        if !self.dirty.load(Ordering::Acquire) {
+            return;
+        }
+        let mut stale = self.stale.lock().unwrap();
+        if stale.archetypes.contains(&from) {
+            stale.entities.insert(id);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[cfg(feature = "clone")]
+    fn clone_empty(&self) -> Box<dyn ErasedIndex> {
+        Box::new(Self::new(self.key, self.unique))
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Indexes {
+    indexes: HashMap<(TypeId, TypeId), Box<dyn ErasedIndex>>,
+}
+
+#[cfg(feature = "clone")]
+impl Clone for Indexes {
+    fn clone(&self) -> Self {
+        Self {
+            indexes: self
+                .indexes
+                .iter()
+                .map(|(k, index)| (*k, index.clone_empty()))
+                .collect(),
+        }
+    }
+}
+
+impl Indexes {
+    pub(crate) fn insert<C: Component, K: IndexKey>(&mut self, index: IndexStorage<C, K>) {
+        self.indexes
+            .insert((TypeId::of::<C>(), TypeId::of::<K>()), Box::new(index));
+    }
+
+    pub(crate) fn remove<C: Component, K: IndexKey>(&mut self) -> bool {
+        self.indexes
+            .remove(&(TypeId::of::<C>(), TypeId::of::<K>()))
+            .is_some()
+    }
+
+    pub(crate) fn get<C: Component, K: IndexKey>(&self) -> Option<&IndexStorage<C, K>> {
+        self.indexes
+            .get(&(TypeId::of::<C>(), TypeId::of::<K>()))
+            .and_then(|index| index.as_any().downcast_ref())
+    }
+
+    #[cfg(feature = "clone")]
+    pub(crate) fn mark_all_dirty(&self) {
+        for index in self.indexes.values() {
+            index.mark_all_dirty();
+        }
+    }
+
+    /// The entity's `components` were inserted or removed
+    pub(crate) fn mark_entity(&self, components: &[TypeId], id: EntityId) {
+        for index in self.indexes.values() {
+            if components.contains(&index.component()) {
+                index.mark_entity(id);
+            }
+        }
+    }
+
+    /// The entity's `archetype` components were removed
+    pub(crate) fn mark_entity_archetype(&self, archetype: &ArchetypeStorage, id: EntityId) {
+        for index in self.indexes.values() {
+            if archetype.components.contains_key(&index.component()) {
+                index.mark_entity(id);
+            }
+        }
+    }
+
+    /// The rows of `archetype` were handed out with mutable access to `components`
+    pub(crate) fn mark_archetype(
+        &self,
+        components: &HashSet<TypeDesc>,
+        archetype: &ArchetypeStorage,
+    ) {
+        for index in self.indexes.values() {
+            let c = index.component();
+            if archetype.components.contains_key(&c) && components.iter().any(|ty| ty.id == c) {
+                index.mark_archetype(archetype.ty);
+            }
+        }
+    }
+
+    /// The entity was handed out with mutable access to `components`
+    pub(crate) fn mark_entity_types(&self, components: &HashSet<TypeDesc>, id: EntityId) {
+        for index in self.indexes.values() {
+            let c = index.component();
+            if components.iter().any(|ty| ty.id == c) {
+                index.mark_entity(id);
+            }
+        }
+    }
+
+    /// The entity left the archetype `from`, stale rows have to follow it
+    pub(crate) fn entity_moved(&self, from: TypeHash, id: EntityId) {
+        for index in self.indexes.values() {
+            index.entity_moved(from, id);
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.indexes.is_empty()
+    }
+}
+
+/// Look up entities by a key computed from their `C` component
+///
+/// Register indexes with [[World::add_index]] or [[World::add_unique_index]].
+/// Panics if the index was not registered.
+pub struct Index<'a, C, K> {
+    entities: &'a HashMap<K, Vec<EntityId>>,
+    _m: PhantomData<C>,
+}
+
+impl<'a, C: Component, K: IndexKey> Index<'a, C, K> {
+    pub(crate) fn new(world: &'a World) -> Option<Self> {
+        let index = world.indexes.get::<C, K>()?;
+        index.ensure_fresh(world);
+        Some(Self {
+            entities: unsafe { &*index.entities.get() },
+            _m: PhantomData,
+        })
+    }
+
+    /// Returns the first entity with the key
+    pub fn get(&self, key: &K) -> Option<EntityId> {
+        self.entities.get(key).and_then(|ids| ids.first().copied())
+    }
+
+    /// Returns every entity with the key
+    pub fn get_all(&self, key: &K) -> &'a [EntityId] {
+        self.entities
+            .get(key)
+            .map(|ids| ids.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entities.contains_key(key)
+    }
+
+    /// Number of distinct keys
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'a K, &'a [EntityId])> {
+        self.entities.iter().map(|(k, ids)| (k, ids.as_slice()))
+    }
+}
+
+unsafe impl<'a, C: Component, K: IndexKey> SystemParam<'a> for Index<'a, C, K> {
+    fn new(db: &'a World, _commands_index: usize, _ticks: SystemTicks) -> Self {
+        Self::new(db).unwrap_or_else(|| {
+            panic!(
+                "Index on {} by {} was not registered",
+                std::any::type_name::<C>(),
+                std::any::type_name::<K>()
+            )
+        })
+    }
+
+    fn components_mut(_set: &mut HashSet<TypeDesc>) {
+        // noop
+    }
+
+    fn resources_mut(_set: &mut HashSet<TypeDesc>) {
+        // noop
+    }
+
+    fn components_const(set: &mut HashSet<TypeDesc>) {
+        set.insert(TypeDesc::of::<C>());
+    }
+
+    fn resources_const(_set: &mut HashSet<TypeDesc>) {
+        // noop
+    }
+}
@@ -1,6 +1,11 @@
-#![feature(const_type_id)]
-
-use std::{any::TypeId, collections::BTreeMap, pin::Pin, ptr::NonNull};
+use std::{
+    any::TypeId,
+    collections::BTreeMap,
+    pin::Pin,
+    ptr::NonNull,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
 
 use archetype::ArchetypeStorage;
 use commands::{EntityCommands, ErasedResourceCommand};
@@ -11,35 +16,60 @@ use resources::ResourceStorage;
 use systems::SystemStage;
 
 pub mod bundle;
+#[cfg(feature = "clone")]
+pub mod checkpoint;
+#[cfg(feature = "serde")]
+pub mod command_log;
 pub mod commands;
 pub mod entity_id;
 pub mod handle_table;
+pub mod index;
 pub mod prelude;
 pub mod query;
 pub mod query_set;
 pub mod resources;
+pub mod state_hash;
+pub mod stats;
 pub mod systems;
+pub mod timings;
 
 mod archetype;
 
 #[cfg(feature = "parallel")]
 mod scheduler;
 
+#[cfg(feature = "parallel")]
+pub mod schedule_graph;
+
 #[cfg(feature = "parallel")]
 pub use rayon;
 
 #[cfg(test)]
 mod world_tests;
 
+// lets the derive macros refer to `::cecs` inside this crate too
+extern crate self as cecs;
+
 type CommandBuffer<T> = std::cell::UnsafeCell<Vec<T>>;
+type Archetypes = BTreeMap<TypeHash, Pin<Box<ArchetypeStorage>>>;
 
 pub struct World {
     pub(crate) entity_ids: EntityIndex,
-    pub(crate) archetypes: BTreeMap<TypeHash, Pin<Box<ArchetypeStorage>>>,
+    pub(crate) archetypes: Archetypes,
     pub(crate) resources: ResourceStorage,
     pub(crate) commands: Vec<CommandBuffer<EntityCommands>>,
     pub(crate) resource_commands: Vec<CommandBuffer<ErasedResourceCommand>>,
     pub(crate) system_stages: Vec<SystemStage<'static>>,
+    /// Remove archetypes that have been empty for this many consecutive ticks
+    pub(crate) archetype_gc_ticks: Option<u32>,
+    pub(crate) timings: timings::Timings,
+    pub(crate) change_tick: AtomicU64,
+    pub(crate) indexes: index::Indexes,
+    pub(crate) component_hashers: state_hash::ComponentHashers,
+    #[cfg(feature = "clone")]
+    pub(crate) checkpoints: checkpoint::Checkpoints,
+    #[cfg(feature = "serde")]
+    pub(crate) command_log: command_log::CommandLogger,
     // for each system: a group of parallel systems
     //
     #[cfg(feature = "parallel")]
@@ -52,24 +82,22 @@ unsafe impl Sync for World {}
 #[cfg(feature = "clone")]
 impl Clone for World {
     fn clone(&self) -> Self {
-        let archetypes = self.archetypes.clone();
+        let (entity_ids, archetypes) = self.copy_entities(ArchetypeStorage::clone);
+        self.copy_with(entity_ids, archetypes, self.resources.clone())
+    }
+}
+
+#[cfg(feature = "clone")]
+impl World {
+    fn copy_with(
+        &self,
+        entity_ids: EntityIndex,
+        archetypes: Archetypes,
+        resources: ResourceStorage,
+    ) -> Self {
         let commands = Vec::default();
         let resource_commands = Vec::default();
 
-        let mut entity_ids = self.entity_ids.clone();
-        for (ptr, row_index, id) in self.entity_ids.metadata.iter() {
-            let ty = unsafe { &**ptr }.ty();
-            let new_arch = &archetypes[&ty];
-            entity_ids
-                .update(
-                    *id,
-                    (NonNull::from(new_arch.as_ref().get_ref()), *row_index),
-                )
-                .unwrap();
-        }
-
-        let resources = self.resources.clone();
-
         let systems = self.system_stages.clone();
 
         #[cfg(feature = "parallel")]
@@ -82,28 +110,37 @@ impl Clone for World {
             resources,
             resource_commands,
             system_stages: systems,
+            archetype_gc_ticks: self.archetype_gc_ticks,
+            timings: self.timings.clone(),
+            change_tick: AtomicU64::new(self.change_tick()),
+            indexes: self.indexes.clone(),
+            component_hashers: self.component_hashers.clone(),
+            checkpoints: self.checkpoints.clone(),
+            #[cfg(feature = "serde")]
+            command_log: self.command_log.clone(),
             #[cfg(feature = "parallel")]
             schedule,
         }
     }
 }
 
-type TypeHash = u64;
+pub type TypeHash = u64;
 
-const fn hash_ty<T: 'static>() -> u64 {
+/// TypeId is no longer a plain u64, so hash it, with a fixed-key hasher to keep archetype hashes
+/// stable between runs of the same build
+fn hash_ty<T: 'static>() -> u64 {
+    use std::hash::{Hash, Hasher};
     let ty = TypeId::of::<T>();
-    // FIXME extreme curse
-    //
-    let ty: u64 = unsafe { std::mem::transmute(ty) };
-    if ty == unsafe { std::mem::transmute(TypeId::of::<()>()) } {
+    if ty == TypeId::of::<()>() {
         // ensure that unit type has hash=0
-        0
-    } else {
-        ty
+        return 0;
     }
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    ty.hash(&mut hasher);
+    hasher.finish()
 }
 
-const VOID_TY: TypeHash = hash_ty::<()>();
+const VOID_TY: TypeHash = 0;
 
 #[derive(Clone, Debug, thiserror::Error)]
 pub enum WorldError {
@@ -113,6 +150,14 @@ pub enum WorldError {
     EntityNotFound,
     #[error("Entity doesn't have specified component")]
     ComponentNotFound,
+    #[error("Stage was not found")]
+    StageNotFound,
+    #[error("System was not found")]
+    SystemNotFound,
+    #[error("Resource was not found")]
+    ResourceNotFound,
+    #[error("Checkpoint was not found")]
+    CheckpointNotFound,
 }
 
 pub type WorldResult<T> = Result<T, WorldError>;
@@ -141,10 +186,6 @@ impl<T: 'static + ParallelComponent> Component for T {}
 
 impl World {
     pub fn new(initial_capacity: u32) -> Self {
-        // FIXME: can't add assert to const fn...
-        // the `hash_ty` function assumes that TypeId is a u64 under the hood
-        debug_assert_eq!(std::mem::size_of::<TypeId>(), std::mem::size_of::<u64>());
-
         let entity_ids = EntityIndex::new(initial_capacity);
 
         let mut result = Self {
@@ -154,6 +195,15 @@ impl World {
             commands: Vec::default(),
             resource_commands: Vec::default(),
             system_stages: Default::default(),
+            archetype_gc_ticks: None,
+            timings: Default::default(),
+            change_tick: AtomicU64::new(1),
+            indexes: Default::default(),
+            component_hashers: Default::default(),
+            #[cfg(feature = "clone")]
+            checkpoints: Default::default(),
+            #[cfg(feature = "serde")]
+            command_log: Default::default(),
             #[cfg(feature = "parallel")]
             schedule: Default::default(),
         };
@@ -176,6 +226,35 @@ impl World {
         Ok(())
     }
 
+    /// Collect the schedule with the component and resource access of each system, and the
+    /// conflicts between them. See [[schedule_graph::ScheduleGraph::write_dot]] and
+    /// [[schedule_graph::ScheduleGraph::write_json]] (with the `serde` feature) for exporting.
+    ///
+    /// Only available with the `parallel` feature, otherwise systems always run in the order they
+    /// were added.
+    #[cfg(feature = "parallel")]
+    pub fn schedule_graph(&self) -> schedule_graph::ScheduleGraph {
+        schedule_graph::ScheduleGraph::collect(self)
+    }
+
+    /// List the component and resource types, and their access kinds, that prevent the two
+    /// systems from running in parallel.
+    ///
+    /// An empty list means that the systems may run in parallel.
+    pub fn explain_conflict<'a, A, PA, RA, B, PB, RB>(
+        system_a: A,
+        system_b: B,
+    ) -> Vec<query::Conflict>
+    where
+        A: systems::IntoSystem<'a, PA, RA>,
+        B: systems::IntoSystem<'a, PB, RB>,
+    {
+        system_a
+            .system()
+            .properties()
+            .conflicts(&system_b.system().properties())
+    }
+
     /// Writes entity ids and their archetype hash
     pub fn write_entities(&self, mut w: impl std::io::Write) -> std::io::Result<()> {
         for (arch, _, id) in self.entity_ids.metadata.iter() {
@@ -206,6 +285,69 @@ impl World {
         Ok(result)
     }
 
+    /// Record the values of `T` set or removed by commands, see [[command_log]]
+    #[cfg(feature = "serde")]
+    pub fn register_logged_component<T>(&mut self)
+    where
+        T: Component + serde::Serialize + serde::de::DeserializeOwned,
+    {
+        self.command_log.register_component::<T>();
+    }
+
+    /// Record the values of `T` inserted or removed by commands, see [[command_log]]
+    #[cfg(feature = "serde")]
+    pub fn register_logged_resource<T>(&mut self)
+    where
+        T: Component + serde::Serialize + serde::de::DeserializeOwned,
+    {
+        self.command_log.register_resource::<T>();
+    }
+
+    /// Start recording applied commands into a new log, discarding the log being recorded
+    ///
+    /// Only changes made by commands are recorded, see [[command_log]] for what's missed
+    #[cfg(feature = "serde")]
+    pub fn start_command_log(&mut self) {
+        self.command_log.start();
+    }
+
+    /// Stop recording, returning the recorded log
+    #[cfg(feature = "serde")]
+    pub fn take_command_log(&mut self) -> Option<command_log::CommandLog> {
+        self.command_log.take()
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn command_log(&self) -> Option<&command_log::CommandLog> {
+        self.command_log.log()
+    }
+
+    /// Re-apply the recorded commands of every tick in order
+    ///
+    /// Systems are not executed. To reproduce a recording, replay it onto a World in the state the
+    /// recording was started from, with the same types registered.
+    #[cfg(feature = "serde")]
+    pub fn replay_command_log(
+        &mut self,
+        log: &command_log::CommandLog,
+    ) -> Result<(), command_log::ReplayError> {
+        for tick in log.ticks.iter() {
+            self.replay_tick(tick)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn replay_tick(
+        &mut self,
+        tick: &command_log::TickLog,
+    ) -> Result<(), command_log::ReplayError> {
+        for cmd in tick.commands.iter() {
+            command_log::replay(self, cmd)?;
+        }
+        Ok(())
+    }
+
     pub fn num_entities(&self) -> usize {
         self.entity_ids.len()
     }
@@ -214,37 +356,66 @@ impl World {
         self.entity_ids.is_valid(id)
     }
 
+    /// Include the values of `T` in [[World::state_hash]]
+    pub fn register_hashed_component<T: Component + std::hash::Hash>(&mut self) {
+        self.component_hashers.register::<T>();
+    }
+
+    /// Hash the entities and components of this World, see [[state_hash]]
+    pub fn state_hash(&self) -> state_hash::StateHash {
+        state_hash::StateHash::collect(self)
+    }
+
+    /// Collect memory usage statistics of this World
+    pub fn stats(&self) -> stats::WorldStats {
+        stats::WorldStats::collect(self)
+    }
+
+    /// Commands issued while applying, e.g. by [[commands::Commands::add]] closures, are applied
+    /// too
     pub fn apply_commands(&mut self) -> WorldResult<()> {
         #[cfg(feature = "tracing")]
         tracing::trace!("• Running commands");
-        let mut commands = std::mem::take(&mut self.commands);
-        for (_i, commands) in commands.iter_mut().enumerate() {
-            #[cfg(feature = "tracing")]
-            tracing::trace!("• Running command list {}", _i);
-            for cmd in commands.get_mut().drain(0..) {
-                cmd.apply(self)?;
+        while self.has_pending_commands() {
+            let mut commands = std::mem::take(&mut self.commands);
+            for (_i, commands) in commands.iter_mut().enumerate() {
+                #[cfg(feature = "tracing")]
+                tracing::trace!("• Running command list {}", _i);
+                for cmd in commands.get_mut().drain(0..) {
+                    cmd.apply(self)?;
+                }
+                #[cfg(feature = "tracing")]
+                tracing::trace!("✓ Running command list {}", _i);
             }
-            #[cfg(feature = "tracing")]
-            tracing::trace!("✓ Running command list {}", _i);
-        }
-        self.commands = commands;
-        let mut commands = std::mem::take(&mut self.resource_commands);
-        for (_i, commands) in commands.iter_mut().enumerate() {
-            #[cfg(feature = "tracing")]
-            tracing::trace!("• Running resource command list {}", _i);
-            for cmd in commands.get_mut().drain(0..) {
-                cmd.apply(self)?;
+            let issued = std::mem::replace(&mut self.commands, commands);
+            append_commands(&mut self.commands, issued);
+            let mut commands = std::mem::take(&mut self.resource_commands);
+            for (_i, commands) in commands.iter_mut().enumerate() {
+                #[cfg(feature = "tracing")]
+                tracing::trace!("• Running resource command list {}", _i);
+                for cmd in commands.get_mut().drain(0..) {
+                    cmd.apply(self)?;
+                }
+                #[cfg(feature = "tracing")]
+                tracing::trace!("✓ Running resource command list {}", _i);
             }
-            #[cfg(feature = "tracing")]
-            tracing::trace!("✓ Running resource command list {}", _i);
+            let issued = std::mem::replace(&mut self.resource_commands, commands);
+            append_commands(&mut self.resource_commands, issued);
         }
-        self.resource_commands = commands;
 
         #[cfg(feature = "tracing")]
         tracing::trace!("✓ Running commands done");
         Ok(())
     }
 
+    fn has_pending_commands(&mut self) -> bool {
+        self.commands.iter_mut().any(|c| !c.get_mut().is_empty())
+            || self
+                .resource_commands
+                .iter_mut()
+                .any(|c| !c.get_mut().is_empty())
+    }
+
     pub fn insert_entity(&mut self) -> WorldResult<EntityId> {
         let id = self
             .entity_ids
@@ -277,6 +448,7 @@ impl World {
             .read(id)
             .map_err(|_| WorldError::EntityNotFound)?;
         unsafe {
+            self.indexes.mark_entity_archetype(archetype.as_ref(), id);
             if let Some(id) = archetype.as_mut().remove(index) {
                 self.entity_ids.update(id, (archetype, index)).unwrap();
             }
@@ -285,6 +457,9 @@ impl World {
         Ok(())
     }
 
+    /// # Panics
+    ///
+    /// If the bundle contains a component type more than once
     pub fn set_bundle<T: Bundle>(&mut self, entity_id: EntityId, bundle: T) -> WorldResult<()> {
         let (mut archetype, mut index) = self
             .entity_ids
@@ -294,6 +469,12 @@ impl World {
 
         if !bundle.can_insert(archetype) {
             let new_hash = T::compute_hash(archetype.ty);
+            assert!(
+                new_hash != archetype.ty,
+                "Bundle {} contains a component type more than once",
+                std::any::type_name::<T>()
+            );
+            self.indexes.entity_moved(archetype.ty, entity_id);
             if !self.archetypes.contains_key(&new_hash) {
                 let new_arch = T::extend(archetype);
                 let (mut res, updated_entity) = self.insert_archetype(archetype, index, new_arch);
@@ -317,6 +498,11 @@ impl World {
             }
         }
         bundle.insert(archetype, index)?;
+        if !self.indexes.is_empty() {
+            let mut components = Vec::new();
+            T::component_ids(&mut components);
+            self.indexes.mark_entity(&components, entity_id);
+        }
         self.entity_ids
             .update(entity_id, (NonNull::from(archetype), index))
             .unwrap();
@@ -345,6 +531,8 @@ impl World {
         if !archetype.contains_column::<T>() {
             return Err(WorldError::ComponentNotFound);
         }
+        self.indexes.mark_entity(&[TypeId::of::<T>()], entity_id);
+        self.indexes.entity_moved(archetype.ty, entity_id);
         let new_ty = archetype.extended_hash::<T>();
         if !self.archetypes.contains_key(&new_ty) {
             let (mut res, updated_entity) =
@@ -378,6 +566,29 @@ impl World {
         Ok(())
     }
 
+    /// Index the `C` components of entities by `key`
+    ///
+    /// Look up entities via the [[index::Index]] system param or [[World::get_index]].
+    /// Replaces the existing index on `C` by `K`.
+    pub fn add_index<C: Component, K: index::IndexKey>(&mut self, key: fn(&C) -> K) {
+        self.indexes.insert(index::IndexStorage::new(key, false));
+    }
+
+    /// Like [[World::add_index]], but keys are expected to be unique.
+    /// Debug builds panic when multiple entities have the same key.
+    pub fn add_unique_index<C: Component, K: index::IndexKey>(&mut self, key: fn(&C) -> K) {
+        self.indexes.insert(index::IndexStorage::new(key, true));
+    }
+
+    /// Returns false if the index doesn't exist
+    pub fn remove_index<C: Component, K: index::IndexKey>(&mut self) -> bool {
+        self.indexes.remove::<C, K>()
+    }
+
+    pub fn get_index<C: Component, K: index::IndexKey>(&self) -> Option<index::Index<'_, C, K>> {
+        index::Index::new(self)
+    }
+
     #[inline(never)]
     #[must_use]
     fn insert_archetype(
@@ -394,8 +605,169 @@ impl World {
         (res, moved_entity)
     }
 
+    /// Remove all empty archetypes, except for the void archetype.
+    ///
+    /// Returns the number of archetypes removed.
+    pub fn gc_archetypes(&mut self) -> usize {
+        self.remove_empty_archetypes(0)
+    }
+
+    /// Automatically remove archetypes at the end of [[World::tick]], once they have been empty
+    /// for `ticks` consecutive ticks.
+    ///
+    /// Pass `None` to disable automatic collection (default).
+    pub fn set_archetype_gc(&mut self, ticks: Option<u32>) {
+        self.archetype_gc_ticks = ticks;
+    }
+
+    /// Copy the entities and their components, pointing the copied ids to the copied archetypes
+    #[cfg(feature = "clone")]
+    pub(crate) fn copy_entities(
+        &self,
+        copy: fn(&ArchetypeStorage) -> ArchetypeStorage,
+    ) -> (EntityIndex, Archetypes) {
+        let archetypes = self
+            .archetypes
+            .iter()
+            .map(|(ty, arch)| (*ty, Box::pin(copy(arch))))
+            .collect::<Archetypes>();
+        let mut entity_ids = self.entity_ids.clone();
+        for (ptr, row_index, id) in self.entity_ids.metadata.iter() {
+            let ty = unsafe { &**ptr }.ty();
+            let new_arch = &archetypes[&ty];
+            entity_ids
+                .update(
+                    *id,
+                    (NonNull::from(new_arch.as_ref().get_ref()), *row_index),
+                )
+                .unwrap();
+        }
+        (entity_ids, archetypes)
+    }
+
+    /// Copy this World, sharing the component columns and resources with it until either World
+    /// mutates them
+    ///
+    /// Columns are copied as a whole when a system or query borrows them mutably, or when entities
+    /// are added to or removed from their archetype. Non-send resources are not forked.
+    #[cfg(feature = "clone")]
+    pub fn fork(&self) -> Self {
+        let (entity_ids, archetypes) = self.copy_entities(ArchetypeStorage::share);
+        self.copy_with(entity_ids, archetypes, self.resources.share())
+    }
+
+    /// Save a copy of the entities, components and resources, to be restored by
+    /// [[World::rollback]]
+    ///
+    /// Keeps the last [[checkpoint::DEFAULT_CHECKPOINT_CAPACITY]] checkpoints by default, the
+    /// oldest is dropped when the buffer is full. Non-send resources are not saved.
+    #[cfg(feature = "clone")]
+    pub fn checkpoint(&mut self) {
+        let mut checkpoints = std::mem::take(&mut self.checkpoints);
+        checkpoints.push(self);
+        self.checkpoints = checkpoints;
+    }
+
+    /// Restore the `ticks_back`-th most recent checkpoint, `rollback(1)` restores the last one.
+    ///
+    /// The restored checkpoint and the ones newer than it are removed.
+    #[cfg(feature = "clone")]
+    pub fn rollback(&mut self, ticks_back: usize) -> WorldResult<()> {
+        let mut checkpoints = std::mem::take(&mut self.checkpoints);
+        let restored = checkpoints.rollback(self, ticks_back);
+        self.checkpoints = checkpoints;
+        if !restored {
+            return Err(WorldError::CheckpointNotFound);
+        }
+        Ok(())
+    }
+
+    /// Drops the oldest checkpoints if there are more than `capacity`
+    #[cfg(feature = "clone")]
+    pub fn set_checkpoint_capacity(&mut self, capacity: usize) {
+        self.checkpoints.set_capacity(capacity);
+    }
+
+    #[cfg(feature = "clone")]
+    pub fn num_checkpoints(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    #[cfg(feature = "clone")]
+    pub fn clear_checkpoints(&mut self) {
+        self.checkpoints.clear();
+    }
+
+    fn remove_empty_archetypes(&mut self, min_empty_ticks: u32) -> usize {
+        let len = self.archetypes.len();
+        // empty archetypes have no entities, so no pointers in `entity_ids` refer to them
+        self.archetypes.retain(|ty, arch| {
+            *ty == VOID_TY || !arch.is_empty() || arch.empty_ticks < min_empty_ticks
+        });
+        let removed = len - self.archetypes.len();
+        #[cfg(feature = "tracing")]
+        tracing::trace!(removed, "Removed empty archetypes");
+        removed
+    }
+
+    fn update_archetype_gc(&mut self) {
+        let ticks = match self.archetype_gc_ticks {
+            Some(t) => t,
+            None => return,
+        };
+        for arch in self.archetypes.values_mut() {
+            if arch.is_empty() {
+                arch.empty_ticks = arch.empty_ticks.saturating_add(1);
+            } else {
+                arch.empty_ticks = 0;
+            }
+        }
+        self.remove_empty_archetypes(ticks);
+    }
+
+    /// Read-only query, see [[World::query_mut]] for mutable access
+    pub fn query<'w, T>(&'w self) -> query::QueryRef<'w, T>
+    where
+        query::ArchQuery<T>: query::QueryFragment<'w> + query::ReadOnlyFragment,
+    {
+        query::QueryRef::new(self)
+    }
+
+    pub fn query_filtered<'w, T, F>(&'w self) -> query::QueryRef<'w, T, F>
+    where
+        query::ArchQuery<T>: query::QueryFragment<'w> + query::ReadOnlyFragment,
+        F: query::filters::Filter,
+    {
+        query::QueryRef::new(self)
+    }
+
+    /// Panics if the query borrows a component type both mutably and immutably
+    ///
+    /// The World stays borrowed for as long as the query or its items are alive:
+    /// ```compile_fail
+    /// use cecs::prelude::*;
+    /// let mut world = World::new(1);
+    /// let q = world.query_mut::<&mut i32>();
+    /// world.query_mut::<&mut i32>();
+    /// q.count();
+    /// ```
+    pub fn query_mut<'w, T>(&'w mut self) -> query::QueryMut<'w, T>
+    where
+        query::ArchQuery<T>: query::QueryFragment<'w>,
+    {
+        query::QueryMut::new(self)
+    }
+
+    pub fn query_filtered_mut<'w, T, F>(&'w mut self) -> query::QueryMut<'w, T, F>
+    where
+        query::ArchQuery<T>: query::QueryFragment<'w>,
+        F: query::filters::Filter,
+    {
+        query::QueryMut::new(self)
+    }
+
     pub fn insert_resource<T: Component>(&mut self, value: T) {
-        self.resources.insert(value);
+        self.resources.insert(value, self.change_tick());
     }
 
     pub fn remove_resource<T: 'static>(&mut self) -> Option<Box<T>> {
@@ -406,21 +778,214 @@ impl World {
         self.resources.fetch::<T>()
     }
 
+    /// Remove resource `T` for the duration of `f`, so both the World and the resource can be
+    /// mutated at the same time.
+    ///
+    /// The resource is reinserted after `f` returns, or panics, and is marked changed.
+    /// If `f` inserts another `T` into the World, it's overwritten.
+    pub fn resource_scope<T: 'static, R>(
+        &mut self,
+        f: impl FnOnce(&mut World, &mut T) -> R,
+    ) -> WorldResult<R> {
+        struct Reinsert<'w> {
+            world: &'w mut World,
+            ty: TypeId,
+            resource: Option<std::cell::UnsafeCell<resources::ErasedResource>>,
+        }
+
+        impl<'w> Drop for Reinsert<'w> {
+            fn drop(&mut self) {
+                let mut resource = self.resource.take().unwrap();
+                resource.get_mut().ticks.changed = self.world.change_tick();
+                self.world.resources.resources.insert(self.ty, resource);
+            }
+        }
+
+        let mut resource = self
+            .resources
+            .resources
+            .remove(&TypeId::of::<T>())
+            .ok_or(WorldError::ResourceNotFound)?;
+        // the value is boxed, so the pointer stays valid while the resource is moved around
+        let value: *mut T = unsafe { resource.get_mut().as_inner_mut::<T>() };
+        let guard = Reinsert {
+            world: self,
+            ty: TypeId::of::<T>(),
+            resource: Some(resource),
+        };
+        Ok(f(&mut *guard.world, unsafe { &mut *value }))
+    }
+
+    /// Non-send resources don't have to be `Send` or `Sync`, they may only be accessed by the
+    /// thread that inserted them, and are not cloned with the World.
+    ///
+    /// Systems accessing them via [[query::resource_query::NonSend]] or
+    /// [[query::resource_query::NonSendMut]] run on the thread calling [[World::tick]].
+    ///
+    /// If the World is dropped by another thread its non-send resources are leaked.
+    pub fn insert_non_send_resource<T: 'static>(&mut self, value: T) {
+        self.resources.insert_non_send(value, self.change_tick());
+    }
+
+    pub fn remove_non_send_resource<T: 'static>(&mut self) -> Option<Box<T>> {
+        self.resources.remove_non_send::<T>()
+    }
+
+    pub fn get_non_send_resource<T: 'static>(&self) -> Option<&T> {
+        self.resources.fetch_non_send::<T>()
+    }
+
+    /// Marks the resource changed
+    pub fn get_non_send_resource_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        let tick = self.change_tick();
+        self.resources
+            .fetch_non_send_mut_with_ticks::<T>()
+            .map(|(value, ticks)| {
+                ticks.changed = tick;
+                value
+            })
+    }
+
+    /// Marks the resource changed
     pub fn get_resource_mut<T: 'static>(&mut self) -> Option<&mut T> {
-        self.resources.fetch_mut::<T>()
+        let tick = self.change_tick();
+        self.resources
+            .fetch_mut_with_ticks::<T>()
+            .map(|(value, ticks)| {
+                ticks.changed = tick;
+                value
+            })
+    }
+
+    /// Change detection counter. Incremented each time a system runs.
+    ///
+    /// Systems observe changes made after their previous run, see [[query::resource_query::Res::is_changed]]
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick.load(Ordering::Relaxed)
     }
 
     /// System stages are executed in the order they were added to the World
-    /// TODO: nicer scheduling API for stages
-    pub fn add_stage(&mut self, stage: SystemStage<'_>) {
-        // # SAFETY
-        // lifetimes are managed by the World instance from now
-        let stage = unsafe { std::mem::transmute(stage) };
+    ///
+    /// Use [[World::insert_stage_before]] or [[World::insert_stage_after]] to place a stage
+    /// relative to another one.
+    ///
+    /// Stored stages may not borrow anything:
+    /// ```compile_fail
+    /// use cecs::prelude::*;
+    /// let mut world = World::new(1);
+    /// let name = String::from("borrowed");
+    /// world.add_stage(SystemStage::serial(name.as_str()));
+    /// ```
+    pub fn add_stage(&mut self, stage: SystemStage<'static>) {
+        self.insert_stage_at(self.system_stages.len(), stage);
+    }
+
+    /// Insert the stage before the first stage named `before`
+    pub fn insert_stage_before(
+        &mut self,
+        before: &str,
+        stage: SystemStage<'static>,
+    ) -> WorldResult<()> {
+        let i = self.stage_index(before).ok_or(WorldError::StageNotFound)?;
+        self.insert_stage_at(i, stage);
+        Ok(())
+    }
+
+    /// Insert the stage after the first stage named `after`
+    pub fn insert_stage_after(
+        &mut self,
+        after: &str,
+        stage: SystemStage<'static>,
+    ) -> WorldResult<()> {
+        let i = self.stage_index(after).ok_or(WorldError::StageNotFound)?;
+        self.insert_stage_at(i + 1, stage);
+        Ok(())
+    }
+
+    /// Remove the first stage named `name`
+    pub fn remove_stage(&mut self, name: &str) -> Option<SystemStage<'static>> {
+        let i = self.stage_index(name)?;
+        #[cfg(feature = "parallel")]
+        self.schedule.remove(i);
+        self.timings.stage_removed(i);
+        Some(self.system_stages.remove(i))
+    }
+
+    /// Replace the first stage named `name`, returning the old stage
+    pub fn replace_stage(
+        &mut self,
+        name: &str,
+        stage: SystemStage<'static>,
+    ) -> WorldResult<SystemStage<'static>> {
+        let i = self.stage_index(name).ok_or(WorldError::StageNotFound)?;
         #[cfg(feature = "parallel")]
         {
-            self.schedule.push(scheduler::schedule(&stage));
+            self.schedule[i] = scheduler::schedule(&stage);
         }
-        self.system_stages.push(stage);
+        self.timings.stage_replaced(i);
+        Ok(std::mem::replace(&mut self.system_stages[i], stage))
+    }
+
+    /// Get the first stage named `name`
+    pub fn get_stage(&self, name: &str) -> Option<&SystemStage<'static>> {
+        self.stage_index(name).map(|i| &self.system_stages[i])
+    }
+
+    /// Get the first stage named `name` for modification.
+    ///
+    /// The stage is rescheduled when the returned guard is dropped.
+    pub fn get_stage_mut(&mut self, name: &str) -> Option<systems::StageMut<'_>> {
+        self.stage_index(name)
+            .map(|index| systems::StageMut { world: self, index })
+    }
+
+    /// Names of the stages in execution order
+    pub fn stage_names(&self) -> impl Iterator<Item = &str> {
+        self.system_stages.iter().map(|s| s.name.as_ref())
+    }
+
+    /// Enable or disable every system named `name`, in every stage and sub-stage.
+    ///
+    /// Disabled systems are skipped when their stage runs.
+    /// Systems are named after their function by default, see [[systems::IntoSystem::named]].
+    pub fn set_system_enabled(&mut self, name: &str, enabled: bool) -> WorldResult<()> {
+        let mut found = false;
+        for stage in self.system_stages.iter_mut() {
+            stage.for_each_system_mut(&mut |sys| {
+                if sys.name == name {
+                    sys.enabled = enabled;
+                    found = true;
+                }
+            });
+        }
+        if !found {
+            return Err(WorldError::SystemNotFound);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(system_name = name, enabled, "Set system enabled");
+        Ok(())
+    }
+
+    /// Returns `None` if no system is named `name`
+    pub fn is_system_enabled(&self, name: &str) -> Option<bool> {
+        self.system_stages
+            .iter()
+            .flat_map(|stage| stage.all_systems())
+            .find(|sys| sys.name == name)
+            .map(|sys| sys.enabled)
+    }
+
+    fn stage_index(&self, name: &str) -> Option<usize> {
+        self.system_stages.iter().position(|s| s.name == name)
+    }
+
+    fn insert_stage_at(&mut self, i: usize, stage: SystemStage<'static>) {
+        #[cfg(feature = "parallel")]
+        {
+            self.schedule.insert(i, scheduler::schedule(&stage));
+        }
+        self.timings.stage_inserted(i);
+        self.system_stages.insert(i, stage);
     }
 
     /// Run a single stage withouth adding it to the World
@@ -444,6 +1009,7 @@ impl World {
 
         // pop the stage after execution, one-shot stages are not stored
         self.system_stages.pop();
+        self.timings.stage_removed(i);
         #[cfg(feature = "parallel")]
         self.schedule.pop();
     }
@@ -460,6 +1026,8 @@ impl World {
     }
 
     pub fn tick(&mut self) {
+        #[cfg(feature = "serde")]
+        self.command_log.begin_tick();
         #[cfg(feature = "parallel")]
         debug_assert_eq!(self.system_stages.len(), self.schedule.len());
         for i in 0..self.system_stages.len() {
@@ -467,15 +1035,49 @@ impl World {
             // apply commands after each stage
             self.apply_commands().unwrap();
         }
+        self.update_archetype_gc();
+    }
+
+    /// Wall-clock timings of the stages and their systems executed by this World
+    pub fn timings(&self) -> &timings::Timings {
+        &self.timings
+    }
+
+    pub fn clear_timings(&mut self) {
+        self.timings.clear();
     }
 
     fn execute_stage(&mut self, i: usize) {
-        self.resize_commands(self.system_stages[i].systems.len());
-        let stage = &self.system_stages[i];
+        let mut path = Vec::with_capacity(4);
+        path.push(i);
+        self.execute_stage_at(&mut path);
+    }
+
+    fn stage_at(&self, path: &[usize]) -> &SystemStage<'static> {
+        stage_at(&self.system_stages, path)
+    }
+
+    #[cfg(feature = "parallel")]
+    fn schedule_at(&self, path: &[usize]) -> &scheduler::Schedule {
+        match path.split_last() {
+            Some((i, [])) => &self.schedule[*i],
+            Some((j, parent)) => &self.stage_at(parent).sub_stages[*j].schedule,
+            None => unreachable!(),
+        }
+    }
+
+    fn execute_stage_at(&mut self, path: &mut Vec<usize>) {
+        // should_run and loop conditions use the first command buffer
+        self.resize_commands(self.stage_at(path).systems.len());
+        let stage = self.stage_at(path);
 
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("stage", stage_name = stage.name.as_ref()).entered();
         #[cfg(feature = "tracing")]
         tracing::trace!(stage_name = stage.name.as_ref(), "• Run stage");
 
+        let start = Instant::now();
+
         for condition in stage.should_run.iter() {
             if !unsafe { run_system(self, condition) } {
                 // stage should not run
@@ -488,31 +1090,155 @@ impl World {
             }
         }
 
-        match stage.systems {
-            systems::StageSystems::Serial(ref systems) => {
-                for system in systems.iter() {
-                    unsafe {
-                        run_system(self, system);
+        let iterations = match stage.looping {
+            systems::StageLoop::Once => Some(1),
+            systems::StageLoop::Times(n) => Some(n),
+            systems::StageLoop::While { .. } => None,
+        };
+        let mut system_times = vec![None; stage.systems.len()];
+        match iterations {
+            Some(n) => {
+                for k in 0..n {
+                    if k > 0 {
+                        self.apply_commands().unwrap();
                     }
+                    self.execute_stage_systems(path, &mut system_times);
                 }
             }
-            #[cfg(feature = "parallel")]
-            systems::StageSystems::Parallel(ref systems) => {
-                let schedule = &self.schedule[i];
-                for group in schedule {
-                    self.execute_systems_parallel(group, systems)
+            None => {
+                let mut k = 0;
+                loop {
+                    let systems::StageLoop::While {
+                        ref condition,
+                        limit,
+                    } = self.stage_at(path).looping
+                    else {
+                        unreachable!()
+                    };
+                    if k >= limit {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(
+                            stage_name = self.stage_at(path).name.as_ref(),
+                            limit,
+                            "Stage loop reached its iteration limit"
+                        );
+                        break;
+                    }
+                    if !unsafe { run_system(self, condition) } {
+                        break;
+                    }
+                    self.execute_stage_systems(path, &mut system_times);
+                    self.apply_commands().unwrap();
+                    k += 1;
                 }
             }
         }
+        let stage_time = start.elapsed();
+        let stage = stage_at(&self.system_stages, path);
         #[cfg(feature = "tracing")]
         tracing::trace!(stage_name = stage.name.as_ref(), "✓ Run stage finished");
+
+        self.timings.record(path, stage, stage_time, &system_times);
+    }
+
+    /// Run a single iteration of the stage: its systems, then its sub-stages
+    ///
+    /// The time of each system is added to `system_times`
+    fn execute_stage_systems(
+        &mut self,
+        path: &mut Vec<usize>,
+        system_times: &mut [Option<std::time::Duration>],
+    ) {
+        let stage = self.stage_at(path);
+        self.resize_commands(stage.systems.len());
+        let stage = self.stage_at(path);
+        let num_groups = match stage.systems {
+            systems::StageSystems::Serial(ref systems) => systems.len(),
+            #[cfg(feature = "parallel")]
+            systems::StageSystems::Parallel(_) => self.schedule_at(path).len(),
+        };
+        let mut record = |j: usize, time| {
+            let total: &mut Option<std::time::Duration> = &mut system_times[j];
+            *total = Some(total.unwrap_or_default() + time);
+        };
+        for g in 0..num_groups {
+            // exclusive systems may mutate the World, so reborrow the stage for each group
+            let stage = self.stage_at(path);
+            let group: &[usize] = match stage.systems {
+                systems::StageSystems::Serial(_) => std::slice::from_ref(&g),
+                #[cfg(feature = "parallel")]
+                systems::StageSystems::Parallel(_) => &self.schedule_at(path)[g],
+            };
+            let systems = stage.systems.as_slice();
+            if let Some(exclusive) = systems[group[0]].exclusive.clone() {
+                // exclusive systems are always scheduled alone
+                debug_assert_eq!(group.len(), 1);
+                let j = group[0];
+                if !systems[j].enabled {
+                    continue;
+                }
+                #[cfg(feature = "tracing")]
+                let _span = tracing::trace_span!(
+                    "exclusive system",
+                    system_name = systems[j].name.as_ref()
+                )
+                .entered();
+                let start = Instant::now();
+                self.run_exclusive_system(exclusive.as_ref());
+                record(j, start.elapsed());
+                // the exclusive system may have used the command buffers
+                self.resize_commands(self.stage_at(path).systems.len());
+                continue;
+            }
+            match stage.systems {
+                systems::StageSystems::Serial(ref systems) => {
+                    if !systems[g].enabled {
+                        continue;
+                    }
+                    let start = Instant::now();
+                    unsafe {
+                        run_system(self, &systems[g]);
+                    }
+                    record(g, start.elapsed());
+                }
+                #[cfg(feature = "parallel")]
+                systems::StageSystems::Parallel(ref systems) => {
+                    for (j, time) in self.execute_systems_parallel(group, systems) {
+                        record(j, time);
+                    }
+                }
+            }
+        }
+        for j in 0..self.stage_at(path).sub_stages.len() {
+            // sub-stages observe the effects of the systems executed before them
+            self.apply_commands().unwrap();
+            path.push(j);
+            self.execute_stage_at(path);
+            path.pop();
+        }
+    }
+
+    /// Apply pending commands before and after the system, so it observes the effects of the
+    /// systems executed before it
+    fn run_exclusive_system(&mut self, system: &systems::ExclusiveSystem) {
+        self.apply_commands().unwrap();
+        (system)(self);
+        self.apply_commands().unwrap();
     }
 
+    /// Ensure that there are at least `len` command buffers, and at least one
+    ///
+    /// Buffers are never removed, they may hold the pending commands of systems executed earlier.
     fn resize_commands(&mut self, len: usize) {
-        self.commands
-            .resize_with(len, std::cell::UnsafeCell::default);
-        self.resource_commands
-            .resize_with(len, std::cell::UnsafeCell::default);
+        let len = len.max(1);
+        if self.commands.len() < len {
+            self.commands
+                .resize_with(len, std::cell::UnsafeCell::default);
+        }
+        if self.resource_commands.len() < len {
+            self.resource_commands
+                .resize_with(len, std::cell::UnsafeCell::default);
+        }
     }
 
     #[cfg(feature = "parallel")]
@@ -520,12 +1246,35 @@ impl World {
         &'a self,
         group: &[usize],
         systems: &[systems::ErasedSystem<()>],
-    ) {
+    ) -> Vec<(usize, std::time::Duration)> {
         use rayon::prelude::*;
 
-        group.par_iter().copied().for_each(|i| unsafe {
-            run_system(self, &systems[i]);
+        let run = |i: usize| {
+            let start = Instant::now();
+            unsafe {
+                run_system(self, &systems[i]);
+            }
+            (i, start.elapsed())
+        };
+        let (local, send): (Vec<usize>, Vec<usize>) = group
+            .iter()
+            .copied()
+            .filter(|i| systems[*i].enabled)
+            .partition(|i| systems[*i].is_non_send());
+        if local.is_empty() {
+            return send.into_par_iter().map(run).collect();
+        }
+        let mut result = Vec::with_capacity(local.len() + send.len());
+        let mut send_result = Vec::new();
+        // the scope's body runs on this thread, spawned tasks on the thread pool
+        rayon::in_place_scope(|s| {
+            s.spawn(|_| {
+                send_result = send.into_par_iter().map(run).collect();
+            });
+            result.extend(local.into_iter().map(run));
         });
+        result.extend(send_result);
+        result
     }
 
     /// Constructs a new [[Commands]] instance with initialized buffers in this world
@@ -535,20 +1284,45 @@ impl World {
     }
 }
 
+/// Keeps the buffers of `lists`, appending the commands of `issued` to them
+fn append_commands<T>(lists: &mut Vec<CommandBuffer<T>>, issued: Vec<CommandBuffer<T>>) {
+    if lists.len() < issued.len() {
+        lists.resize_with(issued.len(), CommandBuffer::default);
+    }
+    for (list, issued) in lists.iter_mut().zip(issued) {
+        list.get_mut().extend(issued.into_inner());
+    }
+}
+
+/// `path` is the index of the top-level stage, followed by the indices of sub-stages
+fn stage_at<'s>(stages: &'s [SystemStage<'static>], path: &[usize]) -> &'s SystemStage<'static> {
+    let mut stage = &stages[path[0]];
+    for j in &path[1..] {
+        stage = &stage.sub_stages[*j].stage;
+    }
+    stage
+}
+
 // # SAFETY
 // this World instance must be borrowed as mutable by the caller, so no other thread should have
 // access to the internals
 //
 // The system's queries must be disjoint to any other concurrently running system's
 unsafe fn run_system<'a, R>(world: &'a World, sys: &'a systems::ErasedSystem<'_, R>) -> R {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("system", system_name = sys.name.as_ref()).entered();
     #[cfg(feature = "tracing")]
     tracing::trace!(system_name = sys.name.as_ref(), "• Running system");
 
     let index = sys.commands_index;
     let execute: &systems::InnerSystem<'_, R> = { std::mem::transmute(sys.execute.as_ref()) };
 
+    let this_run = world.change_tick.fetch_add(1, Ordering::Relaxed);
+    let last_run = sys.last_run.swap(this_run, Ordering::Relaxed);
+    let result = (execute)(world, index, systems::SystemTicks { last_run, this_run });
+
     #[cfg(feature = "tracing")]
     tracing::trace!(system_name = sys.name.as_ref(), "✓ Running system done");
 
-    (execute)(world, index)
+    result
 }
@@ -1,9 +1,10 @@
 pub use crate::bundle::Bundle;
 pub use crate::commands::Commands;
 pub use crate::entity_id::EntityId;
+pub use crate::index::Index;
 pub use crate::query::filters::*;
 pub use crate::query::resource_query::*;
-pub use crate::query::Query;
+pub use crate::query::{AnyOf, Has, Query};
 pub use crate::query_set::*;
-pub use crate::systems::SystemStage;
+pub use crate::systems::{resource_changed, SystemParam, SystemStage};
 pub use crate::World;
@@ -1,35 +1,76 @@
+pub mod combinations;
 pub mod filters;
 pub mod resource_query;
 
 #[cfg(test)]
 mod query_tests;
 
-use crate::{archetype::ArchetypeStorage, entity_id::EntityId, Component, RowIndex, World};
+use crate::{
+    archetype::ArchetypeStorage,
+    entity_id::EntityId,
+    systems::{SystemParam, SystemTicks},
+    Component, RowIndex, World,
+};
+use combinations::{QueryCombinations, QueryCombinationsMut};
 use filters::Filter;
-use std::{any::TypeId, collections::HashSet, marker::PhantomData};
-
-pub(crate) trait WorldQuery<'a> {
-    fn new(db: &'a World, commands_index: usize) -> Self;
-
-    /// List of component types this query needs exclusive access to
-    fn components_mut(set: &mut HashSet<TypeId>);
-    /// List of component types this query needs
-    fn components_const(set: &mut HashSet<TypeId>);
-    /// List of resource types this query needs exclusive access to
-    fn resources_mut(set: &mut HashSet<TypeId>);
-    /// List of resource types this query needs
-    fn resources_const(set: &mut HashSet<TypeId>);
+use std::{
+    any::TypeId,
+    collections::HashSet,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+};
+
+/// [TypeId] of a component or resource, with the type's name attached for diagnostics
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TypeDesc {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub id: TypeId,
+    pub name: &'static str,
+}
+
+impl TypeDesc {
+    pub fn of<T: 'static>() -> Self {
+        Self {
+            id: TypeId::of::<T>(),
+            name: std::any::type_name::<T>(),
+        }
+    }
+}
+
+impl PartialEq for TypeDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for TypeDesc {}
+
+impl Hash for TypeDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
 }
 
 #[derive(Default)]
 pub struct QueryProperties {
-    pub comp_mut: HashSet<TypeId>,
-    pub comp_const: HashSet<TypeId>,
-    pub res_mut: HashSet<TypeId>,
-    pub res_const: HashSet<TypeId>,
+    pub comp_mut: HashSet<TypeDesc>,
+    pub comp_const: HashSet<TypeDesc>,
+    pub res_mut: HashSet<TypeDesc>,
+    pub res_const: HashSet<TypeDesc>,
 }
 
 impl QueryProperties {
+    /// Collect the types accessed by a system parameter
+    pub fn of<'a, T: SystemParam<'a>>() -> Self {
+        let mut result = Self::default();
+        T::components_mut(&mut result.comp_mut);
+        T::components_const(&mut result.comp_const);
+        T::resources_mut(&mut result.res_mut);
+        T::resources_const(&mut result.res_const);
+        result
+    }
+
     pub fn is_disjoint(&self, other: &QueryProperties) -> bool {
         self.comp_mut.is_disjoint(&other.comp_const)
             && self.res_mut.is_disjoint(&other.res_const)
@@ -45,36 +86,138 @@ impl QueryProperties {
         self.comp_const.extend(props.comp_const.into_iter());
         self.res_const.extend(props.res_const.into_iter());
     }
+
+    /// List the types that prevent `self` and `other` from running in parallel, sorted by name
+    pub fn conflicts(&self, other: &QueryProperties) -> Vec<Conflict> {
+        let mut result = Vec::new();
+        collect_conflicts(
+            ConflictKind::Component,
+            (&self.comp_mut, &self.comp_const),
+            (&other.comp_mut, &other.comp_const),
+            &mut result,
+        );
+        collect_conflicts(
+            ConflictKind::Resource,
+            (&self.res_mut, &self.res_const),
+            (&other.res_mut, &other.res_const),
+            &mut result,
+        );
+        result.sort_by(|a, b| (a.kind, a.ty.name).cmp(&(b.kind, b.ty.name)));
+        result
+    }
+}
+
+fn collect_conflicts(
+    kind: ConflictKind,
+    (lhs_mut, lhs_const): (&HashSet<TypeDesc>, &HashSet<TypeDesc>),
+    (rhs_mut, rhs_const): (&HashSet<TypeDesc>, &HashSet<TypeDesc>),
+    out: &mut Vec<Conflict>,
+) {
+    let access = |ty: &TypeDesc, set_mut: &HashSet<TypeDesc>| {
+        if set_mut.contains(ty) {
+            Access::Write
+        } else {
+            Access::Read
+        }
+    };
+    for ty in lhs_mut.union(lhs_const) {
+        let rhs_has = rhs_mut.contains(ty) || rhs_const.contains(ty);
+        if !rhs_has {
+            continue;
+        }
+        let lhs = access(ty, lhs_mut);
+        let rhs = access(ty, rhs_mut);
+        if lhs == Access::Write || rhs == Access::Write {
+            out.push(Conflict {
+                ty: *ty,
+                kind,
+                lhs,
+                rhs,
+            });
+        }
+    }
+}
+
+/// Format conflicts as an indented list, one conflict per line
+pub(crate) fn format_conflicts(conflicts: &[Conflict]) -> String {
+    conflicts
+        .iter()
+        .map(|c| format!("\n\t- {}", c))
+        .collect::<String>()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl std::fmt::Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ConflictKind {
+    Component,
+    Resource,
+}
+
+impl std::fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConflictKind::Component => write!(f, "component"),
+            ConflictKind::Resource => write!(f, "resource"),
+        }
+    }
+}
+
+/// A type accessed by two queries or systems, where at least one of them requires exclusive access
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Conflict {
+    pub ty: TypeDesc,
+    pub kind: ConflictKind,
+    pub lhs: Access,
+    pub rhs: Access,
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} `{}` ({} / {})",
+            self.kind, self.ty.name, self.lhs, self.rhs
+        )
+    }
 }
 
 /// Test if this query is valid and return its properties
 #[inline]
 #[allow(unused)]
-pub(crate) fn ensure_query_valid<'a, T: WorldQuery<'a>>() -> QueryProperties {
-    let mut comp_mut = HashSet::new();
-    let mut comp_const = HashSet::new();
-
-    T::components_mut(&mut comp_mut);
-    T::components_const(&mut comp_const);
-
+pub(crate) fn ensure_query_valid<'a, T: SystemParam<'a>>() -> QueryProperties {
+    let props = QueryProperties::of::<T>();
+    let (comp_mut, comp_const) = (&props.comp_mut, &props.comp_const);
     assert!(
-        comp_mut.is_disjoint(&comp_const),
+        comp_mut.is_disjoint(comp_const),
         "A query may not borrow the same type as both mutable and immutable,
-{}",
-        std::any::type_name::<T>()
+{}
+Types borrowed both ways: {}",
+        std::any::type_name::<T>(),
+        comp_mut
+            .intersection(comp_const)
+            .map(|ty| ty.name)
+            .collect::<Vec<_>>()
+            .join(", ")
     );
-
     // resources do not need asserts here
-    let mut res_mut = HashSet::new();
-    let mut res_const = HashSet::new();
-    T::resources_mut(&mut res_mut);
-    T::resources_const(&mut res_const);
-    QueryProperties {
-        comp_mut,
-        comp_const,
-        res_mut,
-        res_const,
-    }
+    props
 }
 
 pub struct Query<T, F = ()> {
@@ -85,28 +228,28 @@ pub struct Query<T, F = ()> {
 unsafe impl<T, F> Send for Query<T, F> {}
 unsafe impl<T, F> Sync for Query<T, F> {}
 
-impl<'a, T, F> WorldQuery<'a> for Query<T, F>
+unsafe impl<'a, T, F> SystemParam<'a> for Query<T, F>
 where
     ArchQuery<T>: QueryFragment<'a>,
     F: Filter,
 {
-    fn new(db: &'a World, _commands_index: usize) -> Self {
+    fn new(db: &'a World, _commands_index: usize, _ticks: SystemTicks) -> Self {
         Self::new(db)
     }
 
-    fn components_mut(set: &mut HashSet<TypeId>) {
+    fn components_mut(set: &mut HashSet<TypeDesc>) {
         <ArchQuery<T> as QueryFragment>::types_mut(set);
     }
 
-    fn resources_mut(_set: &mut HashSet<TypeId>) {
+    fn resources_mut(_set: &mut HashSet<TypeDesc>) {
         // noop
     }
 
-    fn components_const(set: &mut HashSet<TypeId>) {
+    fn components_const(set: &mut HashSet<TypeDesc>) {
         <ArchQuery<T> as QueryFragment>::types_const(set);
     }
 
-    fn resources_const(_set: &mut HashSet<TypeId>) {
+    fn resources_const(_set: &mut HashSet<TypeDesc>) {
         // noop
     }
 }
@@ -116,13 +259,40 @@ where
     ArchQuery<T>: QueryFragment<'a>,
     F: Filter,
 {
-    pub fn new(world: &'a crate::World) -> Self {
+    /// Outside of systems use [[World::query]] or [[World::query_mut]]
+    pub(crate) fn new(world: &'a crate::World) -> Self {
+        #[cfg(feature = "clone")]
+        Self::make_columns_unique(world);
         Query {
             world: std::ptr::NonNull::from(world),
             _m: PhantomData,
         }
     }
 
+    /// Copy the columns the query may mutate that are shared with a forked World once, so rows
+    /// can be fetched mutably without checking
+    #[cfg(feature = "clone")]
+    fn make_columns_unique(world: &'a crate::World) {
+        let mut types = HashSet::new();
+        <ArchQuery<T> as QueryFragment>::types_mut(&mut types);
+        if types.is_empty() {
+            return;
+        }
+        for arch in world
+            .archetypes
+            .values()
+            .filter(|arch| F::filter(arch) && ArchQuery::<T>::contains(arch))
+        {
+            for ty in types.iter() {
+                if let Some(columns) = arch.components.get(&ty.id) {
+                    // # SAFETY
+                    // the query has exclusive access to the columns it mutates
+                    unsafe { (*columns.get()).make_unique() };
+                }
+            }
+        }
+    }
+
     /// Count the number of entities this query spans
     pub fn count(&self) -> usize {
         unsafe {
@@ -167,6 +337,7 @@ where
     pub fn iter_mut(
         &mut self,
     ) -> impl Iterator<Item = <ArchQuery<T> as QueryFragment<'a>>::ItemMut> {
+        self.mark_indexed_archetypes();
         unsafe {
             self.world
                 .as_ref()
@@ -211,8 +382,39 @@ where
                 return None;
             }
 
-            ArchQuery::<T>::fetch_mut(arch.as_ref(), index)
+            let item = ArchQuery::<T>::fetch_mut(arch.as_ref(), index);
+            if item.is_some() {
+                self.mark_indexed_entity(id);
+            }
+            item
+        }
+    }
+
+    /// Indexes re-key the rows handed out mutably
+    fn mark_indexed_archetypes(&self) {
+        let world = unsafe { self.world.as_ref() };
+        if world.indexes.is_empty() {
+            return;
+        }
+        let mut types = HashSet::new();
+        <ArchQuery<T> as QueryFragment>::types_mut(&mut types);
+        for (_, arch) in world
+            .archetypes
+            .iter()
+            .filter(|(_, arch)| F::filter(arch) && ArchQuery::<T>::contains(arch))
+        {
+            world.indexes.mark_archetype(&types, arch);
+        }
+    }
+
+    fn mark_indexed_entity(&self, id: EntityId) {
+        let world = unsafe { self.world.as_ref() };
+        if world.indexes.is_empty() {
+            return;
         }
+        let mut types = HashSet::new();
+        <ArchQuery<T> as QueryFragment>::types_mut(&mut types);
+        world.indexes.mark_entity_types(&types, id);
     }
 
     pub fn contains(&self, id: EntityId) -> bool {
@@ -229,17 +431,254 @@ where
         }
     }
 
+    /// Iterate over all combinations of `K` distinct items, e.g. every unordered pair for
+    /// `K = 2`. Yields nothing if `K` is 0.
+    pub fn iter_combinations<const K: usize>(&self) -> QueryCombinations<'a, T, F, K> {
+        unsafe { QueryCombinations::new(self.world.as_ref(), self.count()) }
+    }
+
+    /// Consecutive combinations share items, so this is not an `Iterator`, fetch combinations
+    /// one by one with [[QueryCombinationsMut::fetch_next]].
+    pub fn iter_combinations_mut<const K: usize>(
+        &mut self,
+    ) -> QueryCombinationsMut<'_, 'a, T, F, K> {
+        self.mark_indexed_archetypes();
+        unsafe { QueryCombinationsMut::new(self.world.as_ref(), self.count()) }
+    }
+
+    pub fn fetch_many<const N: usize>(
+        &self,
+        ids: [EntityId; N],
+    ) -> Result<[<ArchQuery<T> as QueryFragment<'a>>::Item; N], QueryFetchError> {
+        for id in ids {
+            self.check_fetch(id)?;
+        }
+        Ok(ids.map(|id| self.fetch(id).unwrap()))
+    }
+
+    /// Fetch `N` distinct entities at once
+    ///
+    /// Has the same lifetime limitation as [[Query::fetch_mut]]
+    pub fn fetch_many_mut<const N: usize>(
+        &mut self,
+        ids: [EntityId; N],
+    ) -> Result<[<ArchQuery<T> as QueryFragment<'a>>::ItemMut; N], QueryFetchError> {
+        for (i, id) in ids.iter().enumerate() {
+            if ids[..i].contains(id) {
+                return Err(QueryFetchError::Duplicate(*id));
+            }
+            self.check_fetch(*id)?;
+        }
+        Ok(ids.map(|id| self.fetch_mut(id).unwrap()))
+    }
+
+    fn check_fetch(&self, id: EntityId) -> Result<(), QueryFetchError> {
+        if !unsafe { self.world.as_ref() }.is_id_valid(id) {
+            return Err(QueryFetchError::EntityNotFound(id));
+        }
+        if !self.contains(id) {
+            return Err(QueryFetchError::NotMatched(id));
+        }
+        Ok(())
+    }
+
+    /// Iterate over the items of `ids`. Entities not matched by the query are skipped.
+    pub fn iter_many<'q>(
+        &'q self,
+        ids: impl IntoIterator<Item = EntityId> + 'q,
+    ) -> impl Iterator<Item = <ArchQuery<T> as QueryFragment<'a>>::Item> + 'q {
+        ids.into_iter().filter_map(|id| self.fetch(id))
+    }
+
+    /// Iterate over the items of `ids`. Entities not matched by the query, and repeated ids are
+    /// skipped.
+    ///
+    /// Has the same lifetime limitation as [[Query::iter_mut]]
+    pub fn iter_many_mut<'q>(
+        &'q mut self,
+        ids: impl IntoIterator<Item = EntityId> + 'q,
+    ) -> impl Iterator<Item = <ArchQuery<T> as QueryFragment<'a>>::ItemMut> + 'q {
+        let mut visited = HashSet::new();
+        ids.into_iter()
+            .filter(move |id| visited.insert(*id))
+            .filter_map(|id| self.fetch_mut(id))
+    }
+
     /// fetch the first row of the query
     /// panic if no row was found
+    #[deprecated(note = "use `single`, which fails if more than one row matches")]
     pub fn one(&self) -> <ArchQuery<T> as QueryFragment<'a>>::Item {
         self.iter().next().unwrap()
     }
+
+    /// Fetch the only item matched by the query
+    pub fn single(&self) -> Result<<ArchQuery<T> as QueryFragment<'a>>::Item, QuerySingleError> {
+        self.check_single()?;
+        Ok(self.iter().next().unwrap())
+    }
+
+    /// Fetch the only item matched by the query
+    ///
+    /// Has the same lifetime limitation as [[Query::iter_mut]]
+    pub fn single_mut(
+        &mut self,
+    ) -> Result<<ArchQuery<T> as QueryFragment<'a>>::ItemMut, QuerySingleError> {
+        self.check_single()?;
+        Ok(self.iter_mut().next().unwrap())
+    }
+
+    fn check_single(&self) -> Result<(), QuerySingleError> {
+        match self.count() {
+            0 => Err(QuerySingleError::NoEntities(std::any::type_name::<Self>())),
+            1 => Ok(()),
+            _ => Err(QuerySingleError::MultipleEntities(std::any::type_name::<
+                Self,
+            >())),
+        }
+    }
+}
+
+/// The query is named in the error
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QuerySingleError {
+    #[error("{0} matched no entities")]
+    NoEntities(&'static str),
+    #[error("{0} matched more than one entity")]
+    MultipleEntities(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QueryFetchError {
+    #[error("Entity {0} was not found")]
+    EntityNotFound(EntityId),
+    #[error("Entity {0} is not matched by the query")]
+    NotMatched(EntityId),
+    #[error("Entity {0} was requested more than once")]
+    Duplicate(EntityId),
+}
+
+/// Read-only query borrowing a World, see [[World::query]]
+pub struct QueryRef<'w, T, F = ()> {
+    query: Query<T, F>,
+    _m: PhantomData<&'w World>,
+}
+
+impl<'w, T, F> QueryRef<'w, T, F>
+where
+    ArchQuery<T>: QueryFragment<'w> + ReadOnlyFragment,
+    F: Filter,
+{
+    pub(crate) fn new(world: &'w World) -> Self {
+        Self {
+            query: Query::new(world),
+            _m: PhantomData,
+        }
+    }
+}
+
+impl<'w, T, F> std::ops::Deref for QueryRef<'w, T, F> {
+    type Target = Query<T, F>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.query
+    }
+}
+
+/// Query borrowing a World mutably, see [[World::query_mut]]
+///
+/// Items borrow the World for `'w`, so the methods returning them consume the query.
+pub struct QueryMut<'w, T, F = ()> {
+    query: Query<T, F>,
+    _m: PhantomData<&'w mut World>,
+}
+
+impl<'w, T, F> QueryMut<'w, T, F>
+where
+    ArchQuery<T>: QueryFragment<'w>,
+    F: Filter,
+{
+    pub(crate) fn new(world: &'w mut World) -> Self {
+        ensure_query_valid::<Query<T, F>>();
+        Self {
+            query: Query::new(world),
+            _m: PhantomData,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.query.count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.query.is_empty()
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.query.contains(id)
+    }
+
+    pub fn iter_mut(
+        mut self,
+    ) -> impl Iterator<Item = <ArchQuery<T> as QueryFragment<'w>>::ItemMut> {
+        self.query.iter_mut()
+    }
+
+    pub fn fetch_mut(
+        mut self,
+        id: EntityId,
+    ) -> Option<<ArchQuery<T> as QueryFragment<'w>>::ItemMut> {
+        self.query.fetch_mut(id)
+    }
+
+    pub fn single_mut(
+        mut self,
+    ) -> Result<<ArchQuery<T> as QueryFragment<'w>>::ItemMut, QuerySingleError> {
+        self.query.single_mut()
+    }
+
+    /// See [[Query::iter_combinations_mut]]
+    pub fn iter_combinations_mut<const K: usize>(self) -> QueryCombinationsMut<'w, 'w, T, F, K> {
+        self.query.mark_indexed_archetypes();
+        unsafe { QueryCombinationsMut::new(self.query.world.as_ref(), self.query.count()) }
+    }
 }
 
 pub struct ArchQuery<T> {
     _m: PhantomData<T>,
 }
 
+/// Marks queries that don't borrow any component mutably
+pub trait ReadOnlyFragment {}
+
+impl ReadOnlyFragment for ArchQuery<EntityId> {}
+impl<T> ReadOnlyFragment for ArchQuery<&T> {}
+impl<T> ReadOnlyFragment for ArchQuery<Option<&T>> {}
+
+/// Maps a query to the same query borrowing the World for the shorter lifetime `'s`
+pub trait QueryReborrow<'s> {
+    type Reborrowed;
+}
+
+impl<'s> QueryReborrow<'s> for EntityId {
+    type Reborrowed = EntityId;
+}
+
+impl<'a: 's, 's, T: 'static> QueryReborrow<'s> for &'a T {
+    type Reborrowed = &'s T;
+}
+
+impl<'a: 's, 's, T: 'static> QueryReborrow<'s> for &'a mut T {
+    type Reborrowed = &'s mut T;
+}
+
+impl<'s, T: QueryReborrow<'s>> QueryReborrow<'s> for Option<T> {
+    type Reborrowed = Option<T::Reborrowed>;
+}
+
+impl<'s, T> QueryReborrow<'s> for Has<T> {
+    type Reborrowed = Has<T>;
+}
+
 pub trait QueryFragment<'a> {
     type Item;
     type It: Iterator<Item = Self::Item> + 'a;
@@ -250,8 +689,8 @@ pub trait QueryFragment<'a> {
     fn iter_mut(archetype: &'a ArchetypeStorage) -> Self::ItMut;
     fn fetch(archetype: &'a ArchetypeStorage, index: RowIndex) -> Option<Self::Item>;
     fn fetch_mut(archetype: &'a ArchetypeStorage, index: RowIndex) -> Option<Self::ItemMut>;
-    fn types_mut(set: &mut HashSet<TypeId>);
-    fn types_const(set: &mut HashSet<TypeId>);
+    fn types_mut(set: &mut HashSet<TypeDesc>);
+    fn types_const(set: &mut HashSet<TypeDesc>);
     fn contains(archetype: &'a ArchetypeStorage) -> bool;
 }
 
@@ -266,8 +705,8 @@ pub trait QueryPrimitive<'a> {
     fn fetch_prim(archetype: &'a ArchetypeStorage, index: RowIndex) -> Option<Self::Item>;
     fn fetch_prim_mut(archetype: &'a ArchetypeStorage, index: RowIndex) -> Option<Self::ItemMut>;
     fn contains_prim(archetype: &'a ArchetypeStorage) -> bool;
-    fn types_mut(set: &mut HashSet<TypeId>);
-    fn types_const(set: &mut HashSet<TypeId>);
+    fn types_mut(set: &mut HashSet<TypeDesc>);
+    fn types_const(set: &mut HashSet<TypeDesc>);
 }
 
 impl<'a> QueryPrimitive<'a> for ArchQuery<EntityId> {
@@ -292,11 +731,11 @@ impl<'a> QueryPrimitive<'a> for ArchQuery<EntityId> {
         Self::fetch_prim(archetype, index)
     }
 
-    fn types_mut(_set: &mut HashSet<TypeId>) {
+    fn types_mut(_set: &mut HashSet<TypeDesc>) {
         // noop
     }
 
-    fn types_const(_set: &mut HashSet<TypeId>) {
+    fn types_const(_set: &mut HashSet<TypeDesc>) {
         // noop
         // entity_id is not considered while scheduling
     }
@@ -334,12 +773,12 @@ impl<'a, T: Component> QueryPrimitive<'a> for ArchQuery<Option<&'a T>> {
         Self::fetch_prim(archetype, index)
     }
 
-    fn types_mut(_set: &mut HashSet<TypeId>) {
+    fn types_mut(_set: &mut HashSet<TypeDesc>) {
         // noop
     }
 
-    fn types_const(set: &mut HashSet<TypeId>) {
-        set.insert(TypeId::of::<T>());
+    fn types_const(set: &mut HashSet<TypeDesc>) {
+        set.insert(TypeDesc::of::<T>());
     }
 
     fn contains_prim(_archetype: &'a ArchetypeStorage) -> bool {
@@ -377,11 +816,11 @@ impl<'a, T: Component> QueryPrimitive<'a> for ArchQuery<Option<&'a mut T>> {
         Some(archetype.get_component_mut::<T>(index))
     }
 
-    fn types_mut(set: &mut HashSet<TypeId>) {
-        set.insert(TypeId::of::<T>());
+    fn types_mut(set: &mut HashSet<TypeDesc>) {
+        set.insert(TypeDesc::of::<T>());
     }
 
-    fn types_const(_set: &mut HashSet<TypeId>) {
+    fn types_const(_set: &mut HashSet<TypeDesc>) {
         // noop
     }
 
@@ -417,12 +856,12 @@ impl<'a, T: Component> QueryPrimitive<'a> for ArchQuery<&'a T> {
         archetype.contains_column::<T>()
     }
 
-    fn types_mut(_set: &mut HashSet<TypeId>) {
+    fn types_mut(_set: &mut HashSet<TypeDesc>) {
         // noop
     }
 
-    fn types_const(set: &mut HashSet<TypeId>) {
-        set.insert(TypeId::of::<T>());
+    fn types_const(set: &mut HashSet<TypeDesc>) {
+        set.insert(TypeDesc::of::<T>());
     }
 
     fn iter_prim_mut(archetype: &'a ArchetypeStorage) -> Self::ItMut {
@@ -466,13 +905,59 @@ impl<'a, T: Component> QueryPrimitive<'a> for ArchQuery<&'a mut T> {
         archetype.contains_column::<T>()
     }
 
-    fn types_mut(set: &mut HashSet<TypeId>) {
-        let ty = TypeId::of::<T>();
-        debug_assert!(!set.contains(&ty), "A query may only borrow a type once");
+    fn types_mut(set: &mut HashSet<TypeDesc>) {
+        let ty = TypeDesc::of::<T>();
+        debug_assert!(
+            !set.contains(&ty),
+            "A query may only borrow a type once, `{}` is borrowed multiple times",
+            ty.name
+        );
         set.insert(ty);
     }
 
-    fn types_const(_set: &mut HashSet<TypeId>) {
+    fn types_const(_set: &mut HashSet<TypeDesc>) {
+        // noop
+    }
+}
+
+/// Query item that is `true` if the entity has a `T` component
+///
+/// Does not borrow `T`, so it doesn't constrain the scheduling of systems
+pub struct Has<T>(PhantomData<T>);
+
+impl<T> ReadOnlyFragment for ArchQuery<Has<T>> {}
+
+impl<'a, T: Component> QueryPrimitive<'a> for ArchQuery<Has<T>> {
+    type Item = bool;
+    type It = std::iter::RepeatN<bool>;
+    type ItemMut = bool;
+    type ItMut = std::iter::RepeatN<bool>;
+
+    fn iter_prim(archetype: &'a ArchetypeStorage) -> Self::It {
+        std::iter::repeat_n(archetype.contains_column::<T>(), archetype.len())
+    }
+
+    fn iter_prim_mut(archetype: &'a ArchetypeStorage) -> Self::ItMut {
+        Self::iter_prim(archetype)
+    }
+
+    fn fetch_prim(archetype: &'a ArchetypeStorage, index: RowIndex) -> Option<Self::Item> {
+        ((index as usize) < archetype.len()).then(|| archetype.contains_column::<T>())
+    }
+
+    fn fetch_prim_mut(archetype: &'a ArchetypeStorage, index: RowIndex) -> Option<Self::ItemMut> {
+        Self::fetch_prim(archetype, index)
+    }
+
+    fn contains_prim(_archetype: &'a ArchetypeStorage) -> bool {
+        true
+    }
+
+    fn types_mut(_set: &mut HashSet<TypeDesc>) {
+        // noop
+    }
+
+    fn types_const(_set: &mut HashSet<TypeDesc>) {
         // noop
     }
 }
@@ -506,11 +991,11 @@ where
         Self::contains_prim(archetype)
     }
 
-    fn types_mut(set: &mut HashSet<TypeId>) {
+    fn types_mut(set: &mut HashSet<TypeDesc>) {
         <Self as QueryPrimitive>::types_mut(set);
     }
 
-    fn types_const(set: &mut HashSet<TypeId>) {
+    fn types_const(set: &mut HashSet<TypeDesc>) {
         <Self as QueryPrimitive>::types_const(set);
     }
 }
@@ -528,6 +1013,16 @@ unsafe impl<'a, Inner, Constraint> Sync for TupleIteratorMut<'a, Inner, Constrai
 
 macro_rules! impl_tuple {
     ($($idx: tt : $t: ident),+ $(,)?) => {
+        impl<'s, $($t: QueryReborrow<'s>,)+> QueryReborrow<'s> for ($($t,)+) {
+            type Reborrowed = ($($t::Reborrowed,)+);
+        }
+
+        impl<$($t,)+> ReadOnlyFragment for ArchQuery<($($t,)+)>
+        where
+            $(ArchQuery<$t>: ReadOnlyFragment,)+
+        {
+        }
+
         impl<'a, $($t,)+> Iterator for TupleIterator<
             'a
             , ($( <ArchQuery<$t> as QueryFragment<'a>>::It,)*)
@@ -628,11 +1123,11 @@ macro_rules! impl_tuple {
                     )&&*
             }
 
-            fn types_mut(set: &mut HashSet<TypeId>) {
+            fn types_mut(set: &mut HashSet<TypeDesc>) {
                 $(<ArchQuery<$t> as QueryPrimitive>::types_mut(set));+
             }
 
-            fn types_const(set: &mut HashSet<TypeId>) {
+            fn types_const(set: &mut HashSet<TypeDesc>) {
                 $(<ArchQuery<$t> as QueryPrimitive>::types_const(set));+
             }
         }
@@ -1119,3 +1614,102 @@ impl_tuple!(
     28: T28,
     29: T29
 );
+
+/// Query item that matches entities having at least one of the components in the tuple
+///
+/// e.g. `AnyOf<(&A, &mut B)>` yields `(Option<&A>, Option<&mut B>)`, where at least one of the
+/// items is `Some`
+pub struct AnyOf<T>(PhantomData<T>);
+
+/// Component references that may be used in [[AnyOf]]
+pub trait AnyOfItem {
+    fn contains(archetype: &ArchetypeStorage) -> bool;
+}
+
+impl<T: Component> AnyOfItem for &T {
+    fn contains(archetype: &ArchetypeStorage) -> bool {
+        archetype.contains_column::<T>()
+    }
+}
+
+impl<T: Component> AnyOfItem for &mut T {
+    fn contains(archetype: &ArchetypeStorage) -> bool {
+        archetype.contains_column::<T>()
+    }
+}
+
+macro_rules! impl_any_of {
+    ($($t: ident),+ $(,)?) => {
+        impl<'s, $($t: QueryReborrow<'s>,)+> QueryReborrow<'s> for AnyOf<($($t,)+)> {
+            type Reborrowed = AnyOf<($($t::Reborrowed,)+)>;
+        }
+
+        impl<$($t,)+> ReadOnlyFragment for ArchQuery<AnyOf<($($t,)+)>>
+        where
+            $(ArchQuery<$t>: ReadOnlyFragment,)+
+        {
+        }
+
+        impl<'a, $($t,)+> QueryPrimitive<'a> for ArchQuery<AnyOf<($($t,)+)>>
+        where
+        $(
+            $t: AnyOfItem + 'a,
+            ArchQuery<Option<$t>>: QueryPrimitive<'a>,
+        )+
+        {
+            type Item = ($(<ArchQuery<Option<$t>> as QueryPrimitive<'a>>::Item,)+);
+            // empty in archetypes that contain none of the components
+            type It = std::iter::Take<
+                TupleIterator<'a, ($(<ArchQuery<Option<$t>> as QueryPrimitive<'a>>::It,)+), ($(Option<$t>,)+)>
+            >;
+            type ItemMut = ($(<ArchQuery<Option<$t>> as QueryPrimitive<'a>>::ItemMut,)+);
+            type ItMut = std::iter::Take<
+                TupleIteratorMut<'a, ($(<ArchQuery<Option<$t>> as QueryPrimitive<'a>>::ItMut,)+), ($(Option<$t>,)+)>
+            >;
+
+            fn iter_prim(archetype: &'a ArchetypeStorage) -> Self::It {
+                let n = if Self::contains_prim(archetype) { archetype.len() } else { 0 };
+                TupleIterator(($(ArchQuery::<Option<$t>>::iter_prim(archetype),)+), PhantomData).take(n)
+            }
+
+            fn iter_prim_mut(archetype: &'a ArchetypeStorage) -> Self::ItMut {
+                let n = if Self::contains_prim(archetype) { archetype.len() } else { 0 };
+                TupleIteratorMut(($(ArchQuery::<Option<$t>>::iter_prim_mut(archetype),)+), PhantomData).take(n)
+            }
+
+            fn fetch_prim(archetype: &'a ArchetypeStorage, index: RowIndex) -> Option<Self::Item> {
+                if !Self::contains_prim(archetype) {
+                    return None;
+                }
+                Some(($(ArchQuery::<Option<$t>>::fetch_prim(archetype, index)?,)+))
+            }
+
+            fn fetch_prim_mut(archetype: &'a ArchetypeStorage, index: RowIndex) -> Option<Self::ItemMut> {
+                if !Self::contains_prim(archetype) {
+                    return None;
+                }
+                Some(($(ArchQuery::<Option<$t>>::fetch_prim_mut(archetype, index)?,)+))
+            }
+
+            fn contains_prim(archetype: &'a ArchetypeStorage) -> bool {
+                $($t::contains(archetype))||+
+            }
+
+            fn types_mut(set: &mut HashSet<TypeDesc>) {
+                $(<ArchQuery<Option<$t>> as QueryPrimitive>::types_mut(set);)+
+            }
+
+            fn types_const(set: &mut HashSet<TypeDesc>) {
+                $(<ArchQuery<Option<$t>> as QueryPrimitive>::types_const(set);)+
+            }
+        }
+    };
+}
+
+impl_any_of!(T0, T1);
+impl_any_of!(T0, T1, T2);
+impl_any_of!(T0, T1, T2, T3);
+impl_any_of!(T0, T1, T2, T3, T4);
+impl_any_of!(T0, T1, T2, T3, T4, T5);
+impl_any_of!(T0, T1, T2, T3, T4, T5, T6);
+impl_any_of!(T0, T1, T2, T3, T4, T5, T6, T7);
@@ -0,0 +1,273 @@
+use std::{marker::PhantomData, ops::Bound};
+
+use crate::{archetype::ArchetypeStorage, Archetypes, RowIndex, TypeHash, World};
+
+use super::{filters::Filter, ArchQuery, QueryFragment, QueryReborrow};
+
+#[derive(Clone, Copy)]
+struct Cursor<'a> {
+    ty: TypeHash,
+    archetype: &'a ArchetypeStorage,
+    row: RowIndex,
+    /// Index of the row among all rows matched by the query
+    index: usize,
+}
+
+/// Walks the K-combinations of the rows matched by a query, in lexicographic order
+struct Cursors<'a, T, F, const K: usize> {
+    archetypes: &'a Archetypes,
+    cursors: Option<[Cursor<'a>; K]>,
+    done: bool,
+    /// Number of rows matched by the query
+    len: usize,
+    _m: PhantomData<(T, F)>,
+}
+
+impl<'a, T, F, const K: usize> Cursors<'a, T, F, K>
+where
+    ArchQuery<T>: QueryFragment<'a>,
+    F: Filter,
+{
+    fn new(world: &'a World, len: usize) -> Self {
+        Self {
+            archetypes: &world.archetypes,
+            cursors: None,
+            done: K == 0,
+            len,
+            _m: PhantomData,
+        }
+    }
+
+    fn next_row(archetypes: &'a Archetypes, pos: Option<&Cursor<'a>>) -> Option<Cursor<'a>> {
+        let (range, index) = match pos {
+            Some(pos) if (pos.row as usize + 1) < pos.archetype.len() => {
+                return Some(Cursor {
+                    row: pos.row + 1,
+                    index: pos.index + 1,
+                    ..*pos
+                });
+            }
+            Some(pos) => (
+                archetypes.range((Bound::Excluded(pos.ty), Bound::Unbounded)),
+                pos.index + 1,
+            ),
+            None => (archetypes.range(..), 0),
+        };
+        range
+            .filter(|(_, arch)| {
+                !arch.is_empty() && F::filter(arch) && ArchQuery::<T>::contains(arch)
+            })
+            .map(|(ty, arch)| Cursor {
+                ty: *ty,
+                archetype: arch.as_ref().get_ref(),
+                row: 0,
+                index,
+            })
+            .next()
+    }
+
+    fn advance(&mut self) -> Option<&[Cursor<'a>; K]> {
+        if self.done {
+            return None;
+        }
+        let archetypes = self.archetypes;
+        match self.cursors.as_mut() {
+            None => {
+                let first = Self::next_row(archetypes, None);
+                let mut cursors = match first {
+                    Some(first) => [first; K],
+                    None => {
+                        self.done = true;
+                        return None;
+                    }
+                };
+                for i in 1..K {
+                    match Self::next_row(archetypes, Some(&cursors[i - 1])) {
+                        Some(c) => cursors[i] = c,
+                        None => {
+                            self.done = true;
+                            return None;
+                        }
+                    }
+                }
+                self.cursors = Some(cursors);
+            }
+            Some(cursors) => {
+                // advance the rightmost cursor that has room, then reset the cursors after it
+                let mut i = K;
+                'cursors: loop {
+                    if i == 0 {
+                        self.done = true;
+                        return None;
+                    }
+                    i -= 1;
+                    let Some(c) = Self::next_row(archetypes, Some(&cursors[i])) else {
+                        continue;
+                    };
+                    let mut next = *cursors;
+                    next[i] = c;
+                    for j in i + 1..K {
+                        match Self::next_row(archetypes, Some(&next[j - 1])) {
+                            Some(c) => next[j] = c,
+                            None => continue 'cursors,
+                        }
+                    }
+                    *cursors = next;
+                    break;
+                }
+            }
+        }
+        self.cursors.as_ref()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = if self.done {
+            0
+        } else {
+            match self.cursors.as_ref() {
+                None => binomial(self.len, K),
+                // combinations after the current one
+                Some(cursors) => cursors.iter().enumerate().fold(0u128, |sum, (i, c)| {
+                    sum.saturating_add(binomial(self.len.saturating_sub(c.index + 1), K - i))
+                }),
+            }
+        };
+        match usize::try_from(remaining) {
+            Ok(n) => (n, Some(n)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+}
+
+fn binomial(n: usize, k: usize) -> u128 {
+    if k > n {
+        return 0;
+    }
+    let k = k.min(n - k);
+    let mut result = 1u128;
+    for i in 0..k {
+        // result * (n - i) is divisible by (i + 1)
+        result = match result.checked_mul((n - i) as u128) {
+            Some(r) => r / (i as u128 + 1),
+            None => return u128::MAX,
+        };
+    }
+    result
+}
+
+/// Iterator over all combinations of K distinct items of a query
+///
+/// Created by [[super::Query::iter_combinations]]
+pub struct QueryCombinations<'a, T, F, const K: usize> {
+    cursors: Cursors<'a, T, F, K>,
+}
+
+impl<'a, T, F, const K: usize> QueryCombinations<'a, T, F, K>
+where
+    ArchQuery<T>: QueryFragment<'a>,
+    F: Filter,
+{
+    pub(crate) fn new(world: &'a World, len: usize) -> Self {
+        Self {
+            cursors: Cursors::new(world, len),
+        }
+    }
+}
+
+impl<'a, T, F, const K: usize> Iterator for QueryCombinations<'a, T, F, K>
+where
+    ArchQuery<T>: QueryFragment<'a>,
+    F: Filter,
+{
+    type Item = [<ArchQuery<T> as QueryFragment<'a>>::Item; K];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cursors = self.cursors.advance()?;
+        Some(std::array::from_fn(|i| {
+            ArchQuery::<T>::fetch(cursors[i].archetype, cursors[i].row).unwrap()
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.cursors.size_hint()
+    }
+}
+
+/// Walks all combinations of K distinct items of a query, mutably
+///
+/// Created by [[super::Query::iter_combinations_mut]]
+///
+/// Consecutive combinations share items, so the items of a combination borrow the walker and
+/// only one combination may be alive at a time:
+///
+/// ```
+/// # use cecs::prelude::*;
+/// fn attract(mut q: Query<&mut f32>) {
+///     let mut combinations = q.iter_combinations_mut::<2>();
+///     while let Some([a, b]) = combinations.fetch_next() {
+///         let mid = (*a + *b) / 2.0;
+///         *a = mid;
+///         *b = mid;
+///     }
+/// }
+/// # let mut world = World::new(4);
+/// # world.run_system(attract);
+/// ```
+///
+/// Collecting the combinations would alias the items:
+///
+/// ```compile_fail
+/// # use cecs::prelude::*;
+/// fn collect(mut q: Query<&mut f32>) {
+///     let _all: Vec<[&mut f32; 2]> = q.iter_combinations_mut::<2>().collect();
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use cecs::prelude::*;
+/// fn hold(mut q: Query<&mut f32>) {
+///     let mut combinations = q.iter_combinations_mut::<2>();
+///     let first = combinations.fetch_next();
+///     let second = combinations.fetch_next();
+///     let _both = (first, second);
+/// }
+/// ```
+pub struct QueryCombinationsMut<'q, 'a, T, F, const K: usize> {
+    cursors: Cursors<'a, T, F, K>,
+    /// Borrow of the query
+    _m: PhantomData<&'q mut ()>,
+}
+
+impl<'q, 'a, T, F, const K: usize> QueryCombinationsMut<'q, 'a, T, F, K>
+where
+    ArchQuery<T>: QueryFragment<'a>,
+    F: Filter,
+{
+    pub(crate) fn new(world: &'a World, len: usize) -> Self {
+        Self {
+            cursors: Cursors::new(world, len),
+            _m: PhantomData,
+        }
+    }
+
+    /// Returns the next combination, whose items borrow `self`
+    #[allow(clippy::type_complexity)]
+    pub fn fetch_next<'s>(
+        &'s mut self,
+    ) -> Option<[<ArchQuery<T::Reborrowed> as QueryFragment<'s>>::ItemMut; K]>
+    where
+        T: QueryReborrow<'s>,
+        ArchQuery<T::Reborrowed>: QueryFragment<'s>,
+    {
+        let cursors = self.cursors.advance()?;
+        // the rows of a combination are distinct
+        Some(std::array::from_fn(|i| {
+            ArchQuery::<T::Reborrowed>::fetch_mut(cursors[i].archetype, cursors[i].row).unwrap()
+        }))
+    }
+
+    /// Number of combinations left, see [[Iterator::size_hint]]
+    pub fn size_hint(&self) -> (usize, Option<usize>) {
+        self.cursors.size_hint()
+    }
+}
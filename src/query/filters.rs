@@ -22,6 +22,14 @@ impl<T: Component> Filter for WithOut<T> {
     }
 }
 
+pub struct Not<F>(PhantomData<F>);
+
+impl<F: Filter> Filter for Not<F> {
+    fn filter(archetype: &ArchetypeStorage) -> bool {
+        !F::filter(archetype)
+    }
+}
+
 pub struct Or<X, Y>(PhantomData<(X, Y)>);
 
 impl<X: Filter, Y: Filter> Filter for Or<X, Y> {
@@ -30,6 +38,10 @@ impl<X: Filter, Y: Filter> Filter for Or<X, Y> {
     }
 }
 
+/// Matches archetypes matching any filter of the tuple, e.g.
+/// `AnyFilter<(With<A>, With<B>, With<C>)>`
+pub struct AnyFilter<T>(PhantomData<T>);
+
 impl Filter for () {
     fn filter(_archetype: &ArchetypeStorage) -> bool {
         true
@@ -43,6 +55,12 @@ macro_rules! impl_tuple {
                 $($t::filter(archetype))&&+
             }
         }
+
+        impl<$($t : Filter,)+> Filter for AnyFilter<($($t,)+)> {
+            fn filter(archetype: &ArchetypeStorage) -> bool {
+                $($t::filter(archetype))||+
+            }
+        }
     };
 }
 
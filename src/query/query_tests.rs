@@ -1,9 +1,12 @@
 use crate::{
     entity_id::EntityId,
-    query::filters::{Filter, Or},
+    query::filters::{AnyFilter, Filter, Not, Or},
 };
 
-use super::{filters::With, *};
+use super::{
+    filters::{With, WithOut},
+    *,
+};
 
 #[test]
 fn iter_query_test() {
@@ -169,4 +172,165 @@ fn basic_filter_test() {
     assert!(<(With::<u32>, With::<String>) as Filter>::filter(
         &archetype
     ));
+
+    assert!(Not::<With<i32>>::filter(&archetype));
+    assert!(!Not::<(With<u32>, With<String>)>::filter(&archetype));
+    assert!(AnyFilter::<(With<i32>, With<u64>, With<String>)>::filter(
+        &archetype
+    ));
+    assert!(!AnyFilter::<(With<i32>, With<u64>, Not<With<u32>>)>::filter(&archetype));
+}
+
+#[test]
+fn any_of_and_has_test() {
+    let mut world = World::new(8);
+    let a = world.insert_entity().unwrap();
+    world.set_component(a, 1u32).unwrap();
+    let b = world.insert_entity().unwrap();
+    world.set_component(b, 2u64).unwrap();
+    let c = world.insert_entity().unwrap();
+    world.set_component(c, 3u32).unwrap();
+    world.set_component(c, 4u64).unwrap();
+    // matches neither
+    let d = world.insert_entity().unwrap();
+    world.set_component(d, "d".to_string()).unwrap();
+
+    let q = world.query::<(EntityId, AnyOf<(&u32, &u64)>)>();
+    assert_eq!(q.count(), 3);
+    let mut items = q
+        .iter()
+        .map(|(id, (x, y))| (id, x.copied(), y.copied()))
+        .collect::<Vec<_>>();
+    items.sort_unstable_by_key(|(id, _, _)| *id);
+    assert_eq!(
+        items,
+        vec![
+            (a, Some(1), None),
+            (b, None, Some(2)),
+            (c, Some(3), Some(4))
+        ]
+    );
+    assert!(q.fetch(d).is_none());
+
+    for (x, y) in world.query_mut::<AnyOf<(&mut u32, &u64)>>().iter_mut() {
+        if let (Some(x), Some(y)) = (x, y) {
+            *x += *y as u32;
+        }
+    }
+    assert_eq!(world.get_component::<u32>(c).unwrap(), &7);
+
+    let q = world.query::<(EntityId, Has<u64>)>();
+    assert_eq!(q.count(), 4);
+    let with_u64 = q
+        .iter()
+        .filter(|(_, has)| *has)
+        .map(|(id, _)| id)
+        .collect::<HashSet<_>>();
+    assert_eq!(with_u64, HashSet::from([b, c]));
+    assert_eq!(q.fetch(a), Some((a, false)));
+
+    let mut types = HashSet::new();
+    <ArchQuery<Has<u64>> as QueryFragment>::types_const(&mut types);
+    <ArchQuery<Has<u64>> as QueryFragment>::types_mut(&mut types);
+    assert!(types.is_empty());
+}
+
+#[test]
+fn iter_combinations_test() {
+    let mut world = World::new(8);
+    for i in 0..5u32 {
+        let id = world.insert_entity().unwrap();
+        world.set_component(id, i).unwrap();
+        // spread the entities over multiple archetypes
+        if i % 2 == 0 {
+            world.set_component(id, "even".to_string()).unwrap();
+        }
+    }
+    // not matched by the query
+    let id = world.insert_entity().unwrap();
+    world.set_component(id, 1u64).unwrap();
+
+    let q = world.query::<&u32>();
+    let mut it = q.iter_combinations::<2>();
+    assert_eq!(it.size_hint(), (10, Some(10)));
+    let mut pairs = Vec::new();
+    while let Some([a, b]) = it.next() {
+        pairs.push(if a < b { (*a, *b) } else { (*b, *a) });
+        assert_eq!(it.size_hint().0, 10 - pairs.len());
+    }
+    pairs.sort_unstable();
+    pairs.dedup();
+    assert_eq!(pairs.len(), 10);
+
+    assert_eq!(q.iter_combinations::<3>().count(), 10);
+    assert_eq!(q.iter_combinations::<5>().count(), 1);
+    assert_eq!(q.iter_combinations::<6>().size_hint(), (0, Some(0)));
+    assert_eq!(q.iter_combinations::<6>().count(), 0);
+    assert_eq!(q.iter_combinations::<1>().count(), 5);
+    assert_eq!(q.iter_combinations::<0>().count(), 0);
+}
+
+#[test]
+fn iter_combinations_mut_test() {
+    let mut world = World::new(8);
+    for i in 0..4u32 {
+        let id = world.insert_entity().unwrap();
+        world.set_component(id, i).unwrap();
+        world.set_component(id, 0u64).unwrap();
+        if i == 0 {
+            world.set_component(id, "first".to_string()).unwrap();
+        }
+    }
+
+    fn count_pairs<'a>(mut q: Query<(&'a u32, &'a mut u64)>) {
+        let mut combinations = q.iter_combinations_mut();
+        assert_eq!(combinations.size_hint(), (6, Some(6)));
+        while let Some([(_, a), (_, b)]) = combinations.fetch_next() {
+            *a += 1;
+            *b += 1;
+        }
+        assert_eq!(combinations.size_hint(), (0, Some(0)));
+    }
+    world.run_system(count_pairs);
+
+    // every entity is paired with the 3 others
+    for n in world.query::<&u64>().iter() {
+        assert_eq!(*n, 3);
+    }
+}
+
+#[test]
+fn single_test() {
+    let mut world = World::new(4);
+
+    let err = world.query::<&u32>().single().unwrap_err();
+    assert_eq!(
+        err,
+        QuerySingleError::NoEntities(std::any::type_name::<Query<&u32>>())
+    );
+    assert!(err.to_string().contains("u32"));
+
+    let id = world.insert_entity().unwrap();
+    world.set_component(id, 1u32).unwrap();
+    assert_eq!(world.query::<&u32>().single(), Ok(&1));
+
+    *world.query_mut::<&mut u32>().single_mut().unwrap() = 2;
+    assert_eq!(world.get_component::<u32>(id), Some(&2));
+
+    let id = world.insert_entity().unwrap();
+    world.set_component(id, 3u32).unwrap();
+    assert!(matches!(
+        world.query::<&u32>().single(),
+        Err(QuerySingleError::MultipleEntities(_))
+    ));
+    assert!(world
+        .query_filtered::<&u32, WithOut<String>>()
+        .single()
+        .is_err());
+
+    world.set_component(id, "three".to_string()).unwrap();
+    assert_eq!(
+        world.query_filtered::<&u32, WithOut<String>>().single(),
+        Ok(&2)
+    );
 }
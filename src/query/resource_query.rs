@@ -1,46 +1,67 @@
 use std::{
-    any::TypeId,
     marker::PhantomData,
     ops::{Deref, DerefMut},
 };
 
-use super::WorldQuery;
+use crate::{resources::ResourceTicks, systems::SystemTicks};
+
+use super::TypeDesc;
+use crate::systems::SystemParam;
 
 pub struct Res<'a, T> {
     inner: &'a T,
+    ticks: &'a ResourceTicks,
+    last_run: u64,
     _m: PhantomData<T>,
 }
 
-impl<'a, T: 'static> WorldQuery<'a> for Res<'a, T> {
-    fn new(db: &'a crate::World, _commands_index: usize) -> Self {
-        Self::new(db)
+unsafe impl<'a, T: 'static> SystemParam<'a> for Res<'a, T> {
+    fn new(db: &'a crate::World, _commands_index: usize, ticks: SystemTicks) -> Self {
+        Self::with_ticks(db, ticks)
     }
 
-    fn components_mut(_set: &mut std::collections::HashSet<TypeId>) {
+    fn components_mut(_set: &mut std::collections::HashSet<TypeDesc>) {
         // noop
     }
 
-    fn resources_mut(_set: &mut std::collections::HashSet<TypeId>) {
+    fn resources_mut(_set: &mut std::collections::HashSet<TypeDesc>) {
         // noop
     }
 
-    fn components_const(_set: &mut std::collections::HashSet<TypeId>) {
+    fn components_const(_set: &mut std::collections::HashSet<TypeDesc>) {
         // noop
     }
 
-    fn resources_const(set: &mut std::collections::HashSet<TypeId>) {
-        set.insert(TypeId::of::<T>());
+    fn resources_const(set: &mut std::collections::HashSet<TypeDesc>) {
+        set.insert(TypeDesc::of::<T>());
     }
 }
 
 impl<'a, T: 'static> Res<'a, T> {
+    /// Outside of systems every resource is considered changed
     pub fn new(world: &'a crate::World) -> Self {
-        let inner = world.resources.fetch().unwrap();
+        Self::with_ticks(world, SystemTicks::default())
+    }
+
+    fn with_ticks(world: &'a crate::World, system_ticks: SystemTicks) -> Self {
+        let (inner, ticks) = world.resources.fetch_with_ticks().unwrap();
         Self {
             inner,
+            ticks,
+            last_run: system_ticks.last_run,
             _m: PhantomData,
         }
     }
+
+    /// Returns true if the resource was changed or added since the system last ran
+    pub fn is_changed(&self) -> bool {
+        self.ticks.changed > self.last_run
+    }
+
+    /// Returns true if the resource was added since the system last ran
+    pub fn is_added(&self) -> bool {
+        self.ticks.added > self.last_run
+    }
 }
 
 impl<'a, T: 'static> Deref for Res<'a, T> {
@@ -57,19 +78,51 @@ impl<'a, T: 'static> AsRef<T> for Res<'a, T> {
     }
 }
 
+/// Mutable access to a resource marks it changed
 pub struct ResMut<'a, T> {
     inner: &'a mut T,
+    ticks: &'a mut ResourceTicks,
+    system_ticks: SystemTicks,
     _m: PhantomData<fn() -> &'a mut T>,
 }
 
 impl<'a, T: 'static> ResMut<'a, T> {
     pub fn new(world: &'a crate::World) -> Self {
-        let inner = world.resources.fetch_mut().unwrap();
+        Self::with_ticks(
+            world,
+            SystemTicks {
+                last_run: 0,
+                this_run: world.change_tick(),
+            },
+        )
+    }
+
+    fn with_ticks(world: &'a crate::World, system_ticks: SystemTicks) -> Self {
+        let (inner, ticks) = world.resources.fetch_mut_with_ticks().unwrap();
         Self {
             inner,
+            ticks,
+            system_ticks,
             _m: PhantomData,
         }
     }
+
+    /// Returns true if the resource was changed or added since the system last ran
+    ///
+    /// Changes made by the current system run are included
+    pub fn is_changed(&self) -> bool {
+        self.ticks.changed > self.system_ticks.last_run
+    }
+
+    /// Returns true if the resource was added since the system last ran
+    pub fn is_added(&self) -> bool {
+        self.ticks.added > self.system_ticks.last_run
+    }
+
+    /// Mutable access without marking the resource changed
+    pub fn bypass_change_detection(&mut self) -> &mut T {
+        self.inner
+    }
 }
 
 impl<'a, T: 'static> Deref for ResMut<'a, T> {
@@ -82,6 +135,7 @@ impl<'a, T: 'static> Deref for ResMut<'a, T> {
 
 impl<'a, T: 'static> DerefMut for ResMut<'a, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        self.ticks.changed = self.system_ticks.this_run;
         self.inner
     }
 }
@@ -94,28 +148,197 @@ impl<'a, T: 'static> AsRef<T> for ResMut<'a, T> {
 
 impl<'a, T: 'static> AsMut<T> for ResMut<'a, T> {
     fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+unsafe impl<'a, T: 'static> SystemParam<'a> for ResMut<'a, T> {
+    fn new(db: &'a crate::World, _commands_index: usize, ticks: SystemTicks) -> Self {
+        Self::with_ticks(db, ticks)
+    }
+
+    fn components_mut(_set: &mut std::collections::HashSet<TypeDesc>) {
+        // noop
+    }
+
+    fn resources_mut(set: &mut std::collections::HashSet<TypeDesc>) {
+        set.insert(TypeDesc::of::<T>());
+    }
+
+    fn resources_const(set: &mut std::collections::HashSet<TypeDesc>) {
+        set.insert(TypeDesc::of::<T>());
+    }
+
+    fn components_const(_set: &mut std::collections::HashSet<TypeDesc>) {
+        // noop
+    }
+}
+
+/// Read access to a resource inserted by [[crate::World::insert_non_send_resource]]
+///
+/// Systems with non-send parameters run on the thread calling [[crate::World::tick]]
+pub struct NonSend<'a, T> {
+    inner: &'a T,
+    ticks: &'a ResourceTicks,
+    last_run: u64,
+    _m: PhantomData<*const T>,
+}
+
+impl<'a, T: 'static> NonSend<'a, T> {
+    /// Outside of systems every resource is considered changed
+    pub fn new(world: &'a crate::World) -> Self {
+        Self::with_ticks(world, SystemTicks::default())
+    }
+
+    fn with_ticks(world: &'a crate::World, system_ticks: SystemTicks) -> Self {
+        let (inner, ticks) = world.resources.fetch_non_send_with_ticks().unwrap();
+        Self {
+            inner,
+            ticks,
+            last_run: system_ticks.last_run,
+            _m: PhantomData,
+        }
+    }
+
+    /// Returns true if the resource was changed or added since the system last ran
+    pub fn is_changed(&self) -> bool {
+        self.ticks.changed > self.last_run
+    }
+
+    /// Returns true if the resource was added since the system last ran
+    pub fn is_added(&self) -> bool {
+        self.ticks.added > self.last_run
+    }
+}
+
+impl<'a, T: 'static> Deref for NonSend<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
         self.inner
     }
 }
 
-impl<'a, T: 'static> WorldQuery<'a> for ResMut<'a, T> {
-    fn new(db: &'a crate::World, _commands_index: usize) -> Self {
-        Self::new(db)
+unsafe impl<'a, T: 'static> SystemParam<'a> for NonSend<'a, T> {
+    fn new(db: &'a crate::World, _commands_index: usize, ticks: SystemTicks) -> Self {
+        Self::with_ticks(db, ticks)
     }
 
-    fn components_mut(_set: &mut std::collections::HashSet<TypeId>) {
+    fn components_mut(_set: &mut std::collections::HashSet<TypeDesc>) {
         // noop
     }
 
-    fn resources_mut(set: &mut std::collections::HashSet<TypeId>) {
-        set.insert(TypeId::of::<T>());
+    fn resources_mut(_set: &mut std::collections::HashSet<TypeDesc>) {
+        // noop
+    }
+
+    fn components_const(_set: &mut std::collections::HashSet<TypeDesc>) {
+        // noop
+    }
+
+    fn resources_const(set: &mut std::collections::HashSet<TypeDesc>) {
+        set.insert(non_send_desc::<T>());
+    }
+
+    fn is_non_send() -> bool {
+        true
+    }
+}
+
+/// Write access to a resource inserted by [[crate::World::insert_non_send_resource]]
+///
+/// Systems with non-send parameters run on the thread calling [[crate::World::tick]]
+///
+/// Mutable access to the resource marks it changed
+pub struct NonSendMut<'a, T> {
+    inner: &'a mut T,
+    ticks: &'a mut ResourceTicks,
+    system_ticks: SystemTicks,
+    _m: PhantomData<*mut T>,
+}
+
+impl<'a, T: 'static> NonSendMut<'a, T> {
+    pub fn new(world: &'a crate::World) -> Self {
+        Self::with_ticks(
+            world,
+            SystemTicks {
+                last_run: 0,
+                this_run: world.change_tick(),
+            },
+        )
+    }
+
+    fn with_ticks(world: &'a crate::World, system_ticks: SystemTicks) -> Self {
+        let (inner, ticks) = world.resources.fetch_non_send_mut_with_ticks().unwrap();
+        Self {
+            inner,
+            ticks,
+            system_ticks,
+            _m: PhantomData,
+        }
+    }
+
+    /// Returns true if the resource was changed or added since the system last ran
+    ///
+    /// Changes made by the current system run are included
+    pub fn is_changed(&self) -> bool {
+        self.ticks.changed > self.system_ticks.last_run
+    }
+
+    /// Returns true if the resource was added since the system last ran
+    pub fn is_added(&self) -> bool {
+        self.ticks.added > self.system_ticks.last_run
+    }
+
+    /// Mutable access without marking the resource changed
+    pub fn bypass_change_detection(&mut self) -> &mut T {
+        self.inner
+    }
+}
+
+impl<'a, T: 'static> Deref for NonSendMut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
+}
+
+impl<'a, T: 'static> DerefMut for NonSendMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.ticks.changed = self.system_ticks.this_run;
+        self.inner
     }
+}
 
-    fn resources_const(set: &mut std::collections::HashSet<TypeId>) {
-        set.insert(TypeId::of::<T>());
+unsafe impl<'a, T: 'static> SystemParam<'a> for NonSendMut<'a, T> {
+    fn new(db: &'a crate::World, _commands_index: usize, ticks: SystemTicks) -> Self {
+        Self::with_ticks(db, ticks)
     }
 
-    fn components_const(_set: &mut std::collections::HashSet<TypeId>) {
+    fn components_mut(_set: &mut std::collections::HashSet<TypeDesc>) {
         // noop
     }
+
+    fn resources_mut(set: &mut std::collections::HashSet<TypeDesc>) {
+        set.insert(non_send_desc::<T>());
+    }
+
+    fn resources_const(set: &mut std::collections::HashSet<TypeDesc>) {
+        set.insert(non_send_desc::<T>());
+    }
+
+    fn components_const(_set: &mut std::collections::HashSet<TypeDesc>) {
+        // noop
+    }
+
+    fn is_non_send() -> bool {
+        true
+    }
+}
+
+/// Non-send resources are stored apart from the resources of the same type, so they are tagged
+/// with the type of [[NonSend]] to not conflict with `Res<T>` and `ResMut<T>`
+fn non_send_desc<T: 'static>() -> TypeDesc {
+    TypeDesc::of::<NonSend<'static, T>>()
 }
@@ -1,11 +1,12 @@
 #[cfg(test)]
 mod test;
 
-use std::{any::TypeId, collections::HashSet, marker::PhantomData};
+use std::{collections::HashSet, marker::PhantomData};
 
 use crate::{
     prelude::{Filter, Query},
-    query::{ArchQuery, QueryFragment, WorldQuery},
+    query::{ArchQuery, QueryFragment, TypeDesc},
+    systems::SystemParam,
 };
 
 pub struct QuerySet<Inner> {
@@ -34,21 +35,25 @@ macro_rules! impl_tuple {
             )*
         }
 
-        impl<'a, $($t, $f),*> WorldQuery<'a> for QuerySet<($(Query<$t, $f>),*)>
+        unsafe impl<'a, $($t, $f),*> SystemParam<'a> for QuerySet<($(Query<$t, $f>),*)>
         where
             $(
             ArchQuery<$t>: QueryFragment<'a>,
             $f: Filter,
             )*
         {
-            fn new(db: &'a crate::World, _commands_index: usize) -> Self {
+            fn new(
+                db: &'a crate::World,
+                _commands_index: usize,
+                _ticks: crate::systems::SystemTicks,
+            ) -> Self {
                 Self {
                     inner: ($(Query::<$t, $f>::new(db)),*),
                     _m: PhantomData,
                 }
             }
 
-            fn components_mut(set: &mut HashSet<TypeId>) {
+            fn components_mut(set: &mut HashSet<TypeDesc>) {
                 // sub queries may have overlapping type (that's the point of the QuerySet)
                 // types_mut will panic in this case, so we'll try all in isolation, then
                 // add the types to the output
@@ -62,17 +67,17 @@ macro_rules! impl_tuple {
                 )*
             }
 
-            fn components_const(set: &mut HashSet<TypeId>) {
+            fn components_const(set: &mut HashSet<TypeDesc>) {
                 $(
                     <ArchQuery<$t> as QueryFragment>::types_const(set);
                 )*
             }
 
-            fn resources_mut(_set: &mut HashSet<TypeId>) {
+            fn resources_mut(_set: &mut HashSet<TypeDesc>) {
                 // noop
             }
 
-            fn resources_const(_set: &mut HashSet<TypeId>) {
+            fn resources_const(_set: &mut HashSet<TypeDesc>) {
                 // noop
             }
         }
@@ -1,9 +1,44 @@
-use std::{any::TypeId, cell::UnsafeCell, collections::HashMap};
+use std::{
+    any::TypeId,
+    cell::UnsafeCell,
+    collections::HashMap,
+    sync::Arc,
+    thread::{self, ThreadId},
+};
 
 use crate::Component;
 
 pub struct ResourceStorage {
     pub(crate) resources: HashMap<TypeId, UnsafeCell<ErasedResource>>,
+    pub(crate) non_send: HashMap<TypeId, NonSendResource>,
+}
+
+/// Non-send resources may only be accessed by the thread that inserted them
+pub(crate) struct NonSendResource {
+    resource: UnsafeCell<ErasedResource>,
+    thread: ThreadId,
+}
+
+impl NonSendResource {
+    fn get(&self) -> &UnsafeCell<ErasedResource> {
+        assert_eq!(
+            self.thread,
+            thread::current().id(),
+            "Non-send resources may only be accessed by the thread that inserted them"
+        );
+        &self.resource
+    }
+}
+
+/// The World may be sent to another thread, dropping it there must not drop the resource
+impl Drop for NonSendResource {
+    fn drop(&mut self) {
+        if self.thread != thread::current().id() {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Non-send resource dropped by a foreign thread, leaking it");
+            self.resource.get_mut().leak();
+        }
+    }
 }
 
 #[cfg(feature = "clone")]
@@ -15,6 +50,23 @@ impl Clone for ResourceStorage {
                 .iter()
                 .map(|(id, table)| (*id, UnsafeCell::new(unsafe { &*table.get() }.clone())))
                 .collect(),
+            // non-send resources can not be moved to another World
+            non_send: Default::default(),
+        }
+    }
+}
+
+impl ResourceStorage {
+    /// Copy sharing the resources, which are copied when either storage mutates them
+    #[cfg(feature = "clone")]
+    pub(crate) fn share(&self) -> Self {
+        Self {
+            resources: self
+                .resources
+                .iter()
+                .map(|(id, table)| (*id, UnsafeCell::new(unsafe { &*table.get() }.share())))
+                .collect(),
+            non_send: Default::default(),
         }
     }
 }
@@ -29,16 +81,23 @@ impl ResourceStorage {
     pub fn new() -> Self {
         Self {
             resources: Default::default(),
+            non_send: Default::default(),
         }
     }
 
-    pub fn insert<T: Component>(&mut self, value: T) {
+    /// `tick` is the change tick the resource is added at
+    pub fn insert<T: Component>(&mut self, value: T, tick: u64) {
+        let mut resource = ErasedResource::new(value);
+        resource.ticks = ResourceTicks {
+            added: tick,
+            changed: tick,
+        };
         match self.resources.entry(TypeId::of::<T>()) {
             std::collections::hash_map::Entry::Occupied(mut x) => {
-                x.insert(UnsafeCell::new(ErasedResource::new(value)));
+                x.insert(UnsafeCell::new(resource));
             }
             std::collections::hash_map::Entry::Vacant(e) => {
-                e.insert(UnsafeCell::new(ErasedResource::new(value)));
+                e.insert(UnsafeCell::new(resource));
             }
         }
     }
@@ -55,6 +114,22 @@ impl ResourceStorage {
             .map(|table| unsafe { (*table.get()).as_inner_mut::<T>() })
     }
 
+    pub fn fetch_with_ticks<T: 'static>(&self) -> Option<(&T, &ResourceTicks)> {
+        self.resources.get(&TypeId::of::<T>()).map(|table| unsafe {
+            let table = &*table.get();
+            (table.as_inner::<T>(), &table.ticks)
+        })
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn fetch_mut_with_ticks<T: 'static>(&self) -> Option<(&mut T, &mut ResourceTicks)> {
+        self.resources.get(&TypeId::of::<T>()).map(|table| unsafe {
+            let table = &mut *table.get();
+            let value: *mut T = table.as_inner_mut::<T>();
+            (&mut *value, &mut table.ticks)
+        })
+    }
+
     pub fn remove<T: 'static>(&mut self) -> Option<Box<T>> {
         self.resources
             .remove(&TypeId::of::<T>())
@@ -62,11 +137,82 @@ impl ResourceStorage {
     }
 }
 
+/// Change ticks of a resource, see [[crate::World::change_tick]]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceTicks {
+    pub added: u64,
+    pub changed: u64,
+}
+
+/// Non-send resources are owned by the thread that inserted them
+impl ResourceStorage {
+    pub fn insert_non_send<T: 'static>(&mut self, value: T, tick: u64) {
+        let mut resource = ErasedResource::new_non_send(value);
+        resource.ticks = ResourceTicks {
+            added: tick,
+            changed: tick,
+        };
+        self.non_send.insert(
+            TypeId::of::<T>(),
+            NonSendResource {
+                resource: UnsafeCell::new(resource),
+                thread: thread::current().id(),
+            },
+        );
+    }
+
+    /// Panics if called by a thread other than the one that inserted the resource
+    pub fn fetch_non_send<T: 'static>(&self) -> Option<&T> {
+        self.fetch_non_send_with_ticks().map(|(value, _)| value)
+    }
+
+    /// Panics if called by a thread other than the one that inserted the resource
+    #[allow(clippy::mut_from_ref)]
+    pub fn fetch_non_send_mut<T: 'static>(&self) -> Option<&mut T> {
+        self.fetch_non_send_mut_with_ticks().map(|(value, _)| value)
+    }
+
+    /// Panics if called by a thread other than the one that inserted the resource
+    pub fn fetch_non_send_with_ticks<T: 'static>(&self) -> Option<(&T, &ResourceTicks)> {
+        self.non_send.get(&TypeId::of::<T>()).map(|r| unsafe {
+            let table = &*r.get().get();
+            (table.as_inner::<T>(), &table.ticks)
+        })
+    }
+
+    /// Panics if called by a thread other than the one that inserted the resource
+    #[allow(clippy::mut_from_ref)]
+    pub fn fetch_non_send_mut_with_ticks<T: 'static>(
+        &self,
+    ) -> Option<(&mut T, &mut ResourceTicks)> {
+        self.non_send.get(&TypeId::of::<T>()).map(|r| unsafe {
+            let table = &mut *r.get().get();
+            let value: *mut T = table.as_inner_mut::<T>();
+            (&mut *value, &mut table.ticks)
+        })
+    }
+
+    /// Panics if called by a thread other than the one that inserted the resource
+    pub fn remove_non_send<T: 'static>(&mut self) -> Option<Box<T>> {
+        let mut r = self.non_send.remove(&TypeId::of::<T>())?;
+        r.get();
+        let resource = std::mem::replace(r.resource.get_mut(), ErasedResource::new_non_send(()));
+        Some(unsafe { resource.into_inner() })
+    }
+}
+
 pub(crate) struct ErasedResource {
+    /// `Arc<T>`, shared by forked Worlds until either of them mutates it
     inner: *mut u8,
+    pub(crate) ticks: ResourceTicks,
     finalize: fn(&mut ErasedResource),
     #[cfg(feature = "clone")]
     clone: fn(&ErasedResource) -> ErasedResource,
+    #[cfg(feature = "clone")]
+    share: fn(&ErasedResource) -> ErasedResource,
+    /// Copy the inner value if it's shared
+    #[cfg(feature = "clone")]
+    make_unique: fn(&mut ErasedResource),
 }
 
 impl Drop for ErasedResource {
@@ -84,37 +230,95 @@ impl Clone for ErasedResource {
 
 impl ErasedResource {
     pub fn new<T: Component>(value: T) -> Self {
-        let inner = Box::leak(Box::new(value));
+        Self::from_arc(Arc::new(value))
+    }
+
+    fn from_arc<T: Component>(value: Arc<T>) -> Self {
         Self {
-            inner: (inner as *mut T).cast(),
+            inner: Arc::into_raw(value) as *mut u8,
+            ticks: Default::default(),
             finalize: |resource| unsafe {
                 if !resource.inner.is_null() {
-                    let _inner: Box<T> = Box::from_raw(resource.inner.cast::<T>());
+                    let _inner: Arc<T> = Arc::from_raw(resource.inner.cast::<T>());
                 }
             },
             #[cfg(feature = "clone")]
             clone: |resource| unsafe {
                 let val = resource.as_inner::<T>().clone();
-                ErasedResource::new(val)
+                let mut result = ErasedResource::new(val);
+                result.ticks = resource.ticks;
+                result
+            },
+            #[cfg(feature = "clone")]
+            share: |resource| unsafe {
+                let inner = resource.inner.cast::<T>();
+                Arc::increment_strong_count(inner);
+                let mut result = ErasedResource::from_arc(Arc::from_raw(inner));
+                result.ticks = resource.ticks;
+                result
+            },
+            #[cfg(feature = "clone")]
+            make_unique: |resource| unsafe {
+                // if cloning panics the resource still owns the original
+                let mut inner =
+                    std::mem::ManuallyDrop::new(Arc::from_raw(resource.inner.cast::<T>()));
+                Arc::make_mut(&mut inner);
+                resource.inner = Arc::as_ptr(&inner) as *mut u8;
             },
         }
     }
 
+    pub fn new_non_send<T: 'static>(value: T) -> Self {
+        Self {
+            inner: Arc::into_raw(Arc::new(value)) as *mut u8,
+            ticks: Default::default(),
+            finalize: |resource| unsafe {
+                if !resource.inner.is_null() {
+                    let _inner: Arc<T> = Arc::from_raw(resource.inner.cast::<T>());
+                }
+            },
+            #[cfg(feature = "clone")]
+            clone: |_| unreachable!("Non-send resources are not cloned"),
+            #[cfg(feature = "clone")]
+            share: |_| unreachable!("Non-send resources are not shared"),
+            // never shared
+            #[cfg(feature = "clone")]
+            make_unique: |_| {},
+        }
+    }
+
     /// # SAFETY
     /// Must be called with the same type as `new`
     pub unsafe fn as_inner<T>(&self) -> &T {
         &*self.inner.cast()
     }
 
+    /// Copies the value if it's shared with a forked World
+    ///
     /// # SAFETY
     /// Must be called with the same type as `new`
     pub unsafe fn as_inner_mut<T>(&mut self) -> &mut T {
+        #[cfg(feature = "clone")]
+        (self.make_unique)(self);
         &mut *self.inner.cast()
     }
 
+    #[cfg(feature = "clone")]
+    pub fn share(&self) -> Self {
+        (self.share)(self)
+    }
+
+    /// Forget the value without dropping it
+    fn leak(&mut self) {
+        self.inner = std::ptr::null_mut();
+    }
+
     pub unsafe fn into_inner<T>(mut self) -> Box<T> {
+        #[cfg(feature = "clone")]
+        (self.make_unique)(&mut self);
         let inner = self.inner;
         self.inner = std::ptr::null_mut();
-        Box::from_raw(inner.cast())
+        let inner = Arc::into_inner(Arc::from_raw(inner.cast::<T>())).unwrap();
+        Box::new(inner)
     }
 }
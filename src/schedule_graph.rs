@@ -0,0 +1,320 @@
+//! Export the schedule of a [World] for review
+//!
+//! Requires the `parallel` feature, without it systems always run in the order they were added.
+//! The graph is serializable, and can be written as JSON, with the `serde` feature.
+use std::io::Write;
+
+use crate::{
+    query::{Conflict, TypeDesc},
+    scheduler::Schedule,
+    systems::SystemStage,
+    World,
+};
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ScheduleGraph {
+    pub stages: Vec<StageGraph>,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StageGraph {
+    pub name: String,
+    pub parallel: bool,
+    /// Groups are executed in order, systems within a group may run in parallel
+    pub groups: Vec<Vec<usize>>,
+    pub systems: Vec<SystemNode>,
+    /// Pairs of systems that may not run in parallel
+    ///
+    /// Only computed for parallel stages
+    pub conflicts: Vec<ConflictEdge>,
+    /// Executed in order, after the systems of this stage
+    pub sub_stages: Vec<StageGraph>,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SystemNode {
+    pub name: String,
+    pub group: usize,
+    pub components_mut: Vec<&'static str>,
+    pub components_const: Vec<&'static str>,
+    pub resources_mut: Vec<&'static str>,
+    pub resources_const: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConflictEdge {
+    /// Index of the system that was added first
+    pub from: usize,
+    pub to: usize,
+    /// `lhs` is the access of `from`, `rhs` is the access of `to`
+    pub conflicts: Vec<Conflict>,
+}
+
+impl StageGraph {
+    fn collect(stage: &SystemStage, schedule: &Schedule) -> Self {
+        let systems = stage.systems.as_slice();
+        let props = systems.iter().map(|s| s.properties()).collect::<Vec<_>>();
+        let mut nodes = systems
+            .iter()
+            .zip(props.iter())
+            .map(|(sys, props)| SystemNode {
+                name: sys.name.to_string(),
+                group: 0,
+                components_mut: sorted_names(&props.comp_mut),
+                components_const: sorted_names(&props.comp_const),
+                resources_mut: sorted_names(&props.res_mut),
+                resources_const: sorted_names(&props.res_const),
+            })
+            .collect::<Vec<_>>();
+        for (i, group) in schedule.iter().enumerate() {
+            for j in group {
+                nodes[*j].group = i;
+            }
+        }
+        let mut conflicts = Vec::new();
+        if stage.systems.is_parallel() {
+            for (from, lhs) in props.iter().enumerate() {
+                for (to, rhs) in props.iter().enumerate().skip(from + 1) {
+                    let c = lhs.conflicts(rhs);
+                    if !c.is_empty() {
+                        conflicts.push(ConflictEdge {
+                            from,
+                            to,
+                            conflicts: c,
+                        });
+                    }
+                }
+            }
+        }
+        StageGraph {
+            name: stage.name.to_string(),
+            parallel: stage.systems.is_parallel(),
+            groups: schedule.clone(),
+            systems: nodes,
+            conflicts,
+            sub_stages: stage
+                .sub_stages
+                .iter()
+                .map(|sub| StageGraph::collect(&sub.stage, &sub.schedule))
+                .collect(),
+        }
+    }
+}
+
+fn sorted_names<'a>(set: impl IntoIterator<Item = &'a TypeDesc>) -> Vec<&'static str> {
+    let mut names = set.into_iter().map(|ty| ty.name).collect::<Vec<_>>();
+    names.sort_unstable();
+    names
+}
+
+impl ScheduleGraph {
+    pub(crate) fn collect(world: &World) -> Self {
+        let stages = world
+            .system_stages
+            .iter()
+            .zip(world.schedule.iter())
+            .map(|(stage, schedule)| StageGraph::collect(stage, schedule))
+            .collect();
+        Self { stages }
+    }
+
+    /// Write the schedule in Graphviz DOT format
+    ///
+    /// Stages and groups are clusters, conflicts are dashed edges labeled with the conflicting
+    /// types
+    pub fn write_dot(&self, mut w: impl Write) -> std::io::Result<()> {
+        writeln!(w, "digraph schedule {{")?;
+        writeln!(w, "\tnode [shape=box];")?;
+        for (i, stage) in self.stages.iter().enumerate() {
+            write_dot_stage(&mut w, stage, &i.to_string(), 1)?;
+        }
+        writeln!(w, "}}")?;
+        Ok(())
+    }
+
+    /// Write the schedule as JSON
+    ///
+    /// Conflicting types are written by name
+    #[cfg(feature = "serde")]
+    pub fn write_json(&self, w: impl Write) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(w, self)
+    }
+}
+
+/// Nodes and clusters are identified by the path of their stage, e.g. `s0_1_2` is the third
+/// system of the second sub-stage of the first stage
+fn write_dot_stage(
+    w: &mut impl Write,
+    stage: &StageGraph,
+    path: &str,
+    depth: usize,
+) -> std::io::Result<()> {
+    let indent = "\t".repeat(depth);
+    writeln!(w, "{}subgraph cluster_{} {{", indent, path)?;
+    writeln!(w, "{}\tlabel=\"{}\";", indent, dot_escape(&stage.name))?;
+    for (j, group) in stage.groups.iter().enumerate() {
+        writeln!(w, "{}\tsubgraph cluster_{}_g{} {{", indent, path, j)?;
+        writeln!(w, "{}\t\tlabel=\"Group {}\";", indent, j)?;
+        for k in group {
+            let sys = &stage.systems[*k];
+            let mut label = dot_escape(&sys.name);
+            for (prefix, names) in [
+                ("C mut", &sys.components_mut),
+                ("C", &sys.components_const),
+                ("R mut", &sys.resources_mut),
+                ("R", &sys.resources_const),
+            ] {
+                if !names.is_empty() {
+                    label.push_str(&format!("\\n{}: {}", prefix, dot_escape(&names.join(", "))));
+                }
+            }
+            writeln!(w, "{}\t\ts{}_{} [label=\"{}\"];", indent, path, k, label)?;
+        }
+        writeln!(w, "{}\t}}", indent)?;
+    }
+    for (j, sub) in stage.sub_stages.iter().enumerate() {
+        write_dot_stage(w, sub, &format!("{}_{}", path, j), depth + 1)?;
+    }
+    writeln!(w, "{}}}", indent)?;
+    for edge in stage.conflicts.iter() {
+        let label = edge
+            .conflicts
+            .iter()
+            .map(|c| dot_escape(&c.to_string()))
+            .collect::<Vec<_>>()
+            .join("\\n");
+        writeln!(
+            w,
+            "{}s{}_{} -> s{}_{} [style=dashed, color=red, label=\"{}\"];",
+            indent, path, edge.from, path, edge.to, label
+        )?;
+    }
+    Ok(())
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[derive(Clone)]
+    struct Position;
+    #[derive(Clone)]
+    struct Velocity;
+
+    fn move_sys(_q: Query<(&mut Position, &Velocity)>) {}
+    fn read_sys(_q: Query<&Position>) {}
+    fn accelerate_sys(_q: Query<&mut Velocity>, _r: Res<i32>) {}
+
+    fn world() -> World {
+        let mut world = World::new(4);
+        world.add_stage(
+            SystemStage::parallel("update")
+                .with_system(move_sys)
+                .with_system(read_sys)
+                .with_system(accelerate_sys),
+        );
+        world
+    }
+
+    #[test]
+    fn schedule_graph_conflicts_test() {
+        let graph = world().schedule_graph();
+
+        assert_eq!(graph.stages.len(), 1);
+        let stage = &graph.stages[0];
+        assert_eq!(stage.systems[0].group, 0);
+        assert_eq!(stage.systems[1].group, 1);
+        assert_eq!(
+            stage.systems[2].resources_const,
+            vec![std::any::type_name::<i32>()]
+        );
+
+        // move-read and move-accelerate conflict, read-accelerate do not
+        assert_eq!(stage.conflicts.len(), 2);
+        let edge = &stage.conflicts[0];
+        assert_eq!((edge.from, edge.to), (0, 1));
+        assert_eq!(edge.conflicts.len(), 1);
+        assert_eq!(edge.conflicts[0].ty.name, std::any::type_name::<Position>());
+        assert_eq!(edge.conflicts[0].lhs, crate::query::Access::Write);
+        assert_eq!(edge.conflicts[0].rhs, crate::query::Access::Read);
+    }
+
+    #[test]
+    fn schedule_graph_sub_stages_test() {
+        let mut world = world();
+        world.add_stage(
+            SystemStage::serial("outer").with_sub_stage(
+                SystemStage::parallel("inner")
+                    .with_system(move_sys)
+                    .with_system(read_sys),
+            ),
+        );
+        let graph = world.schedule_graph();
+
+        let outer = &graph.stages[1];
+        assert!(outer.systems.is_empty());
+        assert_eq!(outer.sub_stages.len(), 1);
+        let inner = &outer.sub_stages[0];
+        assert_eq!(inner.name, "inner");
+        assert_eq!(inner.groups.len(), 2);
+        assert_eq!(inner.conflicts.len(), 1);
+
+        let mut dot = Vec::new();
+        graph.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph schedule {"));
+        assert!(dot.contains("s0_0 -> s0_1"));
+        assert!(dot.contains("subgraph cluster_1_0 {"));
+        assert!(dot.contains("s1_0_0 -> s1_0_1"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn schedule_graph_json_test() {
+        let graph = world().schedule_graph();
+
+        let mut json = Vec::new();
+        graph.write_json(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+
+        let stage = &json["stages"][0];
+        assert_eq!(stage["name"], "update");
+        assert_eq!(stage["parallel"], true);
+        assert_eq!(stage["groups"], serde_json::json!([[0], [1, 2]]));
+
+        let systems = stage["systems"].as_array().unwrap();
+        assert_eq!(systems.len(), 3);
+        assert_eq!(
+            systems[0]["components_mut"],
+            serde_json::json!([std::any::type_name::<Position>()])
+        );
+        assert_eq!(
+            systems[2]["resources_const"],
+            serde_json::json!([std::any::type_name::<i32>()])
+        );
+
+        let conflicts = stage["conflicts"].as_array().unwrap();
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0]["from"], 0);
+        assert_eq!(conflicts[0]["to"], 1);
+        assert_eq!(
+            conflicts[0]["conflicts"],
+            serde_json::json!([{
+                "ty": { "name": std::any::type_name::<Position>() },
+                "kind": "Component",
+                "lhs": "Write",
+                "rhs": "Read",
+            }])
+        );
+        assert_eq!(conflicts[1]["from"], 0);
+        assert_eq!(conflicts[1]["to"], 2);
+    }
+}
@@ -1,9 +1,12 @@
-use crate::{query::QueryProperties, systems::SystemStage};
+use crate::systems::{ErasedSystem, SystemStage};
 
 pub type Schedule = Vec<Vec<usize>>;
 
 /// Return list of systems that must run sequentially
 /// All sublist may run in parallel
+///
+/// Exclusive systems are always placed in a group of their own, and act as barriers: systems
+/// added before them are scheduled before, systems added after them are scheduled after.
 pub fn schedule(stage: &SystemStage) -> Schedule {
     if stage.systems.is_empty() {
         return vec![];
@@ -17,21 +20,33 @@ pub fn schedule(stage: &SystemStage) -> Schedule {
         crate::systems::StageSystems::Parallel(s) => s,
     };
 
-    let mut result = vec![vec![0]];
-    let mut history = vec![QueryProperties {
-        comp_mut: (systems[0].components_mut)(),
-        comp_const: (systems[0].components_const)(),
-        res_mut: (systems[0].resources_mut)(),
-        res_const: (systems[0].resources_const)(),
-    }];
-
-    'systems: for (sys_index, sys) in systems.iter().enumerate().skip(1) {
-        let props = QueryProperties {
-            comp_mut: (sys.components_mut)(),
-            res_mut: (sys.resources_mut)(),
-            comp_const: (sys.components_const)(),
-            res_const: (sys.resources_const)(),
-        };
+    let mut result = Vec::new();
+    let mut segment_start = 0;
+    for (sys_index, sys) in systems.iter().enumerate() {
+        if sys.is_exclusive() {
+            schedule_segment(systems, segment_start..sys_index, &mut result);
+            result.push(vec![sys_index]);
+            segment_start = sys_index + 1;
+        }
+    }
+    schedule_segment(systems, segment_start..systems.len(), &mut result);
+    result
+}
+
+fn schedule_segment(
+    systems: &[ErasedSystem<()>],
+    segment: std::ops::Range<usize>,
+    out: &mut Schedule,
+) {
+    if segment.is_empty() {
+        return;
+    }
+    let mut result = vec![vec![segment.start]];
+    let mut history = vec![systems[segment.start].properties()];
+
+    'systems: for sys_index in segment.skip(1) {
+        let sys = &systems[sys_index];
+        let props = sys.properties();
 
         // try to find an existing group this system may run with, in parallel
         // if it fails then we add a new group
@@ -43,10 +58,20 @@ pub fn schedule(stage: &SystemStage) -> Schedule {
             }
         }
 
+        #[cfg(feature = "tracing")]
+        for (i, group) in history.iter().enumerate() {
+            tracing::trace!(
+                system_name = sys.name.as_ref(),
+                group = i,
+                "System can not run in group:{}",
+                crate::query::format_conflicts(&props.conflicts(group))
+            );
+        }
+
         result.push(vec![sys_index]);
         history.push(props);
     }
-    result
+    out.extend(result);
 }
 
 #[cfg(test)]
@@ -78,4 +103,21 @@ mod tests {
         // TODO: this is a bit flaky
         assert_eq!(schedule, vec![vec![0, 1, 4], vec![2], vec![3]]);
     }
+
+    #[test]
+    fn exclusive_system_is_a_barrier_test() {
+        fn system_0(_q: Query<&i32>) {}
+        fn system_1(_q: Query<&u32>) {}
+        fn exclusive(_w: &mut crate::World) {}
+
+        let stage = SystemStage::parallel("barrier")
+            .with_system(system_0)
+            .with_exclusive_system(exclusive)
+            .with_system(system_1)
+            .with_system(system_0);
+
+        let schedule = schedule(&stage);
+
+        assert_eq!(schedule, vec![vec![0], vec![1], vec![2, 3]]);
+    }
 }
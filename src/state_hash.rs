@@ -0,0 +1,203 @@
+//! Hashing the state of a [World], to verify that replicas agree
+//!
+//! The hash covers the handle table, which archetype each entity is in and the values of the
+//! components registered via [[World::register_hashed_component]]. Other components only
+//! contribute their type names. Resources are not hashed.
+//!
+//! Types are identified by their name and entities are combined independent of their order, so
+//! the hash does not depend on `TypeId`s or on the history of the archetypes. It does depend on the
+//! `Hash` implementations of the components, so replicas must run the same build on the same
+//! platform.
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
+
+use crate::{archetype::ArchetypeStorage, Component, RowIndex, World};
+
+type HashFn = fn(&ArchetypeStorage, RowIndex, &mut StableHasher);
+
+#[derive(Default, Clone)]
+pub(crate) struct ComponentHashers {
+    hashers: HashMap<TypeId, HashFn>,
+}
+
+impl ComponentHashers {
+    pub(crate) fn register<T: Component + Hash>(&mut self) {
+        self.hashers
+            .insert(TypeId::of::<T>(), |archetype, row, hasher| {
+                archetype.get_component::<T>(row).unwrap().hash(hasher)
+            });
+    }
+}
+
+/// FNV-1a, which, unlike the std hashers, is guaranteed to be stable between Rust versions
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// Spread the bits of FNV outputs, so summing them does not cancel out similar entities
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58476d1ce4e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StateHash {
+    pub hash: u64,
+    pub handles: u64,
+    /// Non-empty archetypes, sorted by their components
+    pub archetypes: Vec<ArchetypeHash>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArchetypeHash {
+    /// Sorted type names of the components
+    pub components: Vec<String>,
+    pub rows: usize,
+    pub hash: u64,
+}
+
+impl StateHash {
+    /// Components of the archetypes that differ between the two hashes, including archetypes
+    /// missing from either
+    pub fn diverging_archetypes<'a>(&'a self, other: &'a StateHash) -> Vec<&'a [String]> {
+        let mut result = Vec::new();
+        let (mut lhs, mut rhs) = (self.archetypes.iter(), other.archetypes.iter());
+        let (mut l, mut r) = (lhs.next(), rhs.next());
+        loop {
+            match (l, r) {
+                (None, None) => break,
+                (Some(a), Some(b)) if a.components == b.components => {
+                    if a.hash != b.hash {
+                        result.push(a.components.as_slice());
+                    }
+                    l = lhs.next();
+                    r = rhs.next();
+                }
+                (Some(a), b) if b.map(|b| a.components < b.components).unwrap_or(true) => {
+                    result.push(a.components.as_slice());
+                    l = lhs.next();
+                }
+                (_, Some(b)) => {
+                    result.push(b.components.as_slice());
+                    r = rhs.next();
+                }
+                (Some(_), None) => unreachable!(),
+            }
+        }
+        result
+    }
+
+    pub(crate) fn collect(world: &World) -> Self {
+        let mut archetypes = world
+            .archetypes
+            .values()
+            .filter(|arch| !arch.is_empty())
+            .map(|arch| hash_archetype(world, arch))
+            .collect::<Vec<_>>();
+        archetypes.sort_unstable_by(|a, b| a.components.cmp(&b.components));
+
+        let handles = hash_handles(world);
+        let mut hasher = StableHasher::default();
+        hasher.write_u64(handles);
+        for arch in archetypes.iter() {
+            hasher.write_u64(arch.hash);
+        }
+        Self {
+            hash: hasher.finish(),
+            handles,
+            archetypes,
+        }
+    }
+}
+
+fn hash_handles(world: &World) -> u64 {
+    let handles = &world.entity_ids.handles;
+    let mut hasher = StableHasher::default();
+    hasher.write_u64(handles.len() as u64);
+    // allocated ids, in index order
+    let mut ids = world
+        .entity_ids
+        .metadata
+        .iter()
+        .map(|(_, _, id)| *id)
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    for id in ids {
+        hasher.write_u32(id.index());
+        hasher.write_u32(id.gen());
+    }
+    // the free list determines the ids allocated next
+    for id in handles.free_list() {
+        hasher.write_u32(id.index());
+        hasher.write_u32(id.gen());
+    }
+    hasher.finish()
+}
+
+fn hash_archetype(world: &World, archetype: &ArchetypeStorage) -> ArchetypeHash {
+    let mut columns = archetype
+        .components
+        .iter()
+        .map(|(ty, col)| {
+            (
+                unsafe { &*col.get() }.ty_name,
+                world.component_hashers.hashers.get(ty).copied(),
+            )
+        })
+        .collect::<Vec<_>>();
+    columns.sort_unstable_by_key(|(name, _)| *name);
+
+    let mut rows = 0u64;
+    for (row, id) in archetype.entities.iter().enumerate() {
+        let mut hasher = StableHasher::default();
+        hasher.write_u32(id.index());
+        hasher.write_u32(id.gen());
+        for (_, hash) in columns.iter() {
+            if let Some(hash) = hash {
+                hash(archetype, row as RowIndex, &mut hasher);
+            }
+        }
+        rows = rows.wrapping_add(mix(hasher.finish()));
+    }
+
+    let mut hasher = StableHasher::default();
+    for (name, _) in columns.iter() {
+        hasher.write(name.as_bytes());
+        hasher.write_u8(0xff);
+    }
+    hasher.write_u64(archetype.len() as u64);
+    hasher.write_u64(rows);
+    ArchetypeHash {
+        components: columns
+            .into_iter()
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        rows: archetype.len(),
+        hash: hasher.finish(),
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+
+use crate::{TypeHash, World};
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct WorldStats {
+    pub archetypes: Vec<ArchetypeStats>,
+    /// Totals of each component type across all archetypes, keyed by type name
+    pub components: BTreeMap<&'static str, ComponentStats>,
+    pub handles: HandleTableStats,
+    pub resources: usize,
+    /// Resources inserted by [[World::insert_non_send_resource]], not included in `resources`
+    pub non_send_resources: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ArchetypeStats {
+    pub ty: TypeHash,
+    pub rows: usize,
+    /// Capacity of the entity id column
+    pub entities_capacity: usize,
+    pub columns: Vec<ColumnStats>,
+}
+
+impl ArchetypeStats {
+    /// Total bytes allocated by this archetype's columns, including spare capacity
+    pub fn allocated_bytes(&self) -> usize {
+        self.entities_capacity * std::mem::size_of::<crate::entity_id::EntityId>()
+            + self
+                .columns
+                .iter()
+                .map(|c| c.allocated_bytes())
+                .sum::<usize>()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ColumnStats {
+    pub ty_name: &'static str,
+    pub item_size: usize,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl ColumnStats {
+    /// Bytes used by live rows
+    pub fn bytes(&self) -> usize {
+        self.len * self.item_size
+    }
+
+    /// Bytes allocated but not used by any row
+    pub fn spare_bytes(&self) -> usize {
+        (self.capacity - self.len) * self.item_size
+    }
+
+    pub fn allocated_bytes(&self) -> usize {
+        self.capacity * self.item_size
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ComponentStats {
+    /// Number of archetypes containing this component
+    pub archetypes: usize,
+    pub rows: usize,
+    pub bytes: usize,
+    pub spare_bytes: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HandleTableStats {
+    pub capacity: usize,
+    /// Number of allocated handles
+    pub len: usize,
+    pub free_list_len: usize,
+}
+
+impl WorldStats {
+    pub(crate) fn collect(world: &World) -> Self {
+        let mut components = BTreeMap::<&'static str, ComponentStats>::new();
+        let archetypes = world
+            .archetypes
+            .values()
+            .map(|arch| {
+                let columns = arch
+                    .components
+                    .values()
+                    .map(|col| {
+                        let col = unsafe { &*col.get() };
+                        ColumnStats {
+                            ty_name: col.ty_name,
+                            item_size: col.item_size,
+                            len: arch.len(),
+                            capacity: col.capacity(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                for col in columns.iter() {
+                    let total = components.entry(col.ty_name).or_default();
+                    total.archetypes += 1;
+                    total.rows += col.len;
+                    total.bytes += col.bytes();
+                    total.spare_bytes += col.spare_bytes();
+                }
+                ArchetypeStats {
+                    ty: arch.ty(),
+                    rows: arch.len(),
+                    entities_capacity: arch.entities.capacity(),
+                    columns,
+                }
+            })
+            .collect();
+
+        let handles = &world.entity_ids.handles;
+        Self {
+            archetypes,
+            components,
+            handles: HandleTableStats {
+                capacity: handles.capacity(),
+                len: handles.len(),
+                free_list_len: handles.free_list_len(),
+            },
+            resources: world.resources.resources.len(),
+            non_send_resources: world.resources.non_send.len(),
+        }
+    }
+}
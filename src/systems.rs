@@ -1,15 +1,88 @@
-use std::{any::TypeId, borrow::Cow, collections::HashSet, rc::Rc};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-use crate::{query::WorldQuery, World};
+use crate::{
+    query::{resource_query::Res, Conflict, QueryProperties, TypeDesc},
+    World,
+};
 
-pub type InnerSystem<'a, R> = dyn Fn(&'a World, usize) -> R + 'a;
+/// Derive [SystemParam](trait@SystemParam) for structs whose fields are system parameters
+///
+/// The struct may have at most one lifetime parameter, which is the lifetime of the World borrow.
+///
+/// ```
+/// use cecs::prelude::*;
+///
+/// #[derive(Clone)]
+/// struct Map(Vec<u32>);
+/// #[derive(Clone)]
+/// struct Position(usize);
+///
+/// #[derive(SystemParam)]
+/// struct Pathfinder<'a> {
+///     map: Res<'a, Map>,
+///     positions: Query<&'a Position>,
+/// }
+///
+/// impl Pathfinder<'_> {
+///     fn cost(&self) -> u32 {
+///         self.positions.iter().map(|p| self.map.0[p.0]).sum()
+///     }
+/// }
+///
+/// let mut world = World::new(4);
+/// world.insert_resource(Map(vec![1, 2, 3]));
+/// for i in 0..3 {
+///     let id = world.insert_entity().unwrap();
+///     world.set_component(id, Position(i)).unwrap();
+/// }
+/// assert_eq!(world.run_system(|p: Pathfinder| p.cost()), 6);
+/// ```
+pub use cecs_macros::SystemParam;
+
+pub type InnerSystem<'a, R> = dyn Fn(&'a World, usize, SystemTicks) -> R + 'a;
 pub type ShouldRunSystem<'a> = InnerSystem<'a, bool>;
+pub type ExclusiveSystem = dyn Fn(&mut World);
+
+/// Maximum number of iterations of a stage looping with [[SystemStage::with_loop_while]]
+pub const DEFAULT_LOOP_LIMIT: u32 = 1024;
 
 #[derive(Clone)]
 pub struct SystemStage<'a> {
     pub name: Cow<'a, str>,
     pub should_run: Vec<ErasedSystem<'a, bool>>,
     pub systems: StageSystems<'a>,
+    pub looping: StageLoop<'a>,
+    /// Executed in order, after the systems of this stage
+    pub(crate) sub_stages: Vec<SubStage<'a>>,
+}
+
+/// How many times a stage runs when it's executed
+///
+/// Commands are applied between iterations
+#[derive(Clone, Default)]
+pub enum StageLoop<'a> {
+    #[default]
+    Once,
+    Times(u32),
+    /// The condition is executed before each iteration, the loop stops when it returns false, or
+    /// after `limit` iterations
+    While {
+        condition: ErasedSystem<'a, bool>,
+        limit: u32,
+    },
+}
+
+#[derive(Clone)]
+pub(crate) struct SubStage<'a> {
+    pub(crate) stage: SystemStage<'a>,
+    #[cfg(feature = "parallel")]
+    pub(crate) schedule: crate::scheduler::Schedule,
 }
 
 #[derive(Clone)]
@@ -48,6 +121,14 @@ impl<'a> StageSystems<'a> {
         }
     }
 
+    pub fn as_mut_slice(&mut self) -> &mut [ErasedSystem<'a, ()>] {
+        match self {
+            StageSystems::Serial(v) => v.as_mut_slice(),
+            #[cfg(feature = "parallel")]
+            StageSystems::Parallel(v) => v.as_mut_slice(),
+        }
+    }
+
     /// Returns `true` if the stage systems is [`Serial`].
     ///
     /// [`Serial`]: StageSystems::Serial
@@ -75,6 +156,8 @@ impl<'a> SystemStage<'a> {
             name: name.into(),
             should_run: Vec::with_capacity(1),
             systems: StageSystems::Serial(Vec::with_capacity(4)),
+            looping: StageLoop::Once,
+            sub_stages: Vec::new(),
         }
     }
 
@@ -87,6 +170,8 @@ impl<'a> SystemStage<'a> {
             systems: StageSystems::Parallel(Vec::with_capacity(4)),
             #[cfg(not(feature = "parallel"))]
             systems: StageSystems::Serial(Vec::with_capacity(4)),
+            looping: StageLoop::Once,
+            sub_stages: Vec::new(),
         }
     }
 
@@ -100,6 +185,14 @@ impl<'a> SystemStage<'a> {
     }
 
     pub fn with_system<S, P>(mut self, system: S) -> Self
+    where
+        S: IntoSystem<'a, P, ()>,
+    {
+        self.add_system(system);
+        self
+    }
+
+    pub fn add_system<S, P>(&mut self, system: S) -> &mut Self
     where
         S: IntoSystem<'a, P, ()>,
     {
@@ -118,19 +211,349 @@ impl<'a> SystemStage<'a> {
         self.systems.push(system);
         self
     }
+
+    /// Exclusive systems have mutable access to the World.
+    ///
+    /// They run alone, after every system added before them finished and before the systems
+    /// added after them start. Commands are applied before and after exclusive systems.
+    ///
+    /// Exclusive systems must not add or remove the stages of the World they're running in.
+    pub fn with_exclusive_system<F>(mut self, system: F) -> Self
+    where
+        F: Fn(&mut World) + 'static,
+    {
+        self.add_exclusive_system(system);
+        self
+    }
+
+    pub fn add_exclusive_system<F>(&mut self, system: F) -> &mut Self
+    where
+        F: Fn(&mut World) + 'static,
+    {
+        self.systems.push(ErasedSystem::exclusive(system));
+        self
+    }
+
+    pub fn with_named_exclusive_system<F, N>(mut self, name: N, system: F) -> Self
+    where
+        F: Fn(&mut World) + 'static,
+        N: Into<Cow<'a, str>>,
+    {
+        self.systems
+            .push(ErasedSystem::exclusive(system).with_name(name));
+        self
+    }
+
+    /// Sub-stages are executed after the systems of this stage, in the order they were added.
+    ///
+    /// Commands are applied before each sub-stage.
+    pub fn with_sub_stage(mut self, stage: SystemStage<'a>) -> Self {
+        self.add_sub_stage(stage);
+        self
+    }
+
+    pub fn add_sub_stage(&mut self, stage: SystemStage<'a>) -> &mut Self {
+        self.sub_stages.push(SubStage {
+            #[cfg(feature = "parallel")]
+            schedule: crate::scheduler::schedule(&stage),
+            stage,
+        });
+        self
+    }
+
+    pub fn sub_stages(&self) -> impl Iterator<Item = &SystemStage<'a>> {
+        self.sub_stages.iter().map(|s| &s.stage)
+    }
+
+    /// Sub-stages of a stage owned by a World are rescheduled when its [[StageMut]] is dropped
+    pub fn sub_stages_mut(&mut self) -> impl Iterator<Item = &mut SystemStage<'a>> {
+        self.sub_stages.iter_mut().map(|s| &mut s.stage)
+    }
+
+    /// Run the stage, including its sub-stages, `n` times each time it's executed
+    pub fn with_loop_count(mut self, n: u32) -> Self {
+        self.looping = StageLoop::Times(n);
+        self
+    }
+
+    /// Run the stage, including its sub-stages, for as long as `condition` returns true, but at
+    /// most [[DEFAULT_LOOP_LIMIT]] times
+    ///
+    /// The condition is checked before each iteration, after the commands of the previous
+    /// iteration have been applied.
+    pub fn with_loop_while<S, P>(self, condition: S) -> Self
+    where
+        S: IntoSystem<'a, P, bool>,
+    {
+        self.with_loop_while_limit(condition, DEFAULT_LOOP_LIMIT)
+    }
+
+    /// Like [[SystemStage::with_loop_while]], but stops after `limit` iterations
+    pub fn with_loop_while_limit<S, P>(mut self, condition: S, limit: u32) -> Self
+    where
+        S: IntoSystem<'a, P, bool>,
+    {
+        self.looping = StageLoop::While {
+            condition: condition.system(),
+            limit,
+        };
+        self
+    }
+
+    /// Systems of this stage, followed by the systems of its sub-stages, recursively
+    pub fn all_systems(&self) -> Box<dyn Iterator<Item = &ErasedSystem<'a, ()>> + '_> {
+        Box::new(
+            self.systems
+                .as_slice()
+                .iter()
+                .chain(self.sub_stages().flat_map(|s| s.all_systems())),
+        )
+    }
+
+    /// Recompute the schedules of the sub-stages, recursively
+    #[cfg(feature = "parallel")]
+    pub(crate) fn reschedule_sub_stages(&mut self) {
+        for sub in self.sub_stages.iter_mut() {
+            sub.stage.reschedule_sub_stages();
+            sub.schedule = crate::scheduler::schedule(&sub.stage);
+        }
+    }
+
+    pub(crate) fn for_each_system_mut(&mut self, f: &mut impl FnMut(&mut ErasedSystem<'a, ()>)) {
+        self.systems.as_mut_slice().iter_mut().for_each(&mut *f);
+        for sub in self.sub_stages.iter_mut() {
+            sub.stage.for_each_system_mut(f);
+        }
+    }
+}
+
+/// Mutable access to a stage owned by a [[World]]
+///
+/// The stage is rescheduled when this guard is dropped
+pub struct StageMut<'w> {
+    pub(crate) world: &'w mut World,
+    pub(crate) index: usize,
+}
+
+impl<'w> Deref for StageMut<'w> {
+    type Target = SystemStage<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.world.system_stages[self.index]
+    }
+}
+
+impl<'w> DerefMut for StageMut<'w> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.world.system_stages[self.index]
+    }
+}
+
+impl<'w> Drop for StageMut<'w> {
+    fn drop(&mut self) {
+        #[cfg(feature = "parallel")]
+        {
+            let stage = &mut self.world.system_stages[self.index];
+            stage.reschedule_sub_stages();
+            let schedule = crate::scheduler::schedule(stage);
+            self.world.schedule[self.index] = schedule;
+        }
+        let stage = &self.world.system_stages[self.index];
+        self.world.timings.stage_changed(self.index, stage);
+    }
+}
+
+/// Change ticks of a system execution, see [[World::change_tick]]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemTicks {
+    /// Tick of the previous execution of the system, 0 if it hasn't run yet
+    pub last_run: u64,
+    pub this_run: u64,
+}
+
+/// Parameter of a system, fetched from the World every time the system runs
+///
+/// Derive it for structs composed of other parameters, or implement it by hand to wrap them.
+/// Tuples of parameters are parameters too, which lifts the limit on the number of parameters a
+/// system may take.
+///
+/// # Safety
+///
+/// The scheduler uses the listed types to decide which systems may run in parallel, and the
+/// parameter is created from a shared World while other systems run:
+///
+/// - `components_mut` and `resources_mut` must list every component and resource the parameter
+///   may mutate, `components_const` and `resources_const` every one it may only read
+/// - `is_non_send` must return true if the parameter accesses non-send resources
+/// - `conflicts` must report the conflicting accesses between the parts of the parameter
+///
+/// Parameters composed only of other parameters, like the ones `#[derive(SystemParam)]`
+/// generates, uphold this by forwarding to their parts.
+pub unsafe trait SystemParam<'a> {
+    /// `commands_index` identifies the command buffer of the running system, see
+    /// [Commands](crate::commands::Commands)
+    fn new(db: &'a World, commands_index: usize, ticks: SystemTicks) -> Self;
+
+    /// List of component types this query needs exclusive access to
+    fn components_mut(set: &mut HashSet<TypeDesc>);
+    /// List of component types this query needs
+    fn components_const(set: &mut HashSet<TypeDesc>);
+    /// List of resource types this query needs exclusive access to
+    fn resources_mut(set: &mut HashSet<TypeDesc>);
+    /// List of resource types this query needs
+    fn resources_const(set: &mut HashSet<TypeDesc>);
+    /// Systems with non-send parameters must run on the thread calling [[World::tick]]
+    fn is_non_send() -> bool {
+        false
+    }
+    /// Conflicting accesses between the parts of a composite parameter, see
+    /// [[add_param_conflicts]]
+    ///
+    /// Creating a system with a conflicting parameter panics in debug builds.
+    fn conflicts() -> Vec<Conflict> {
+        Vec::new()
+    }
+}
+
+/// Add the accesses of `T`, a part of a composite parameter, to `props`, and its conflicts with
+/// the previous parts to `conflicts`
+pub fn add_param_conflicts<'a, T: SystemParam<'a>>(
+    props: &mut QueryProperties,
+    conflicts: &mut Vec<Conflict>,
+) {
+    conflicts.extend(T::conflicts());
+    let p = QueryProperties::of::<T>();
+    conflicts.extend(p.conflicts(props));
+    props.extend(p);
+}
+
+macro_rules! impl_param_tuple {
+    ($($t: ident),+ $(,)*) => {
+        unsafe impl<'a, $($t: SystemParam<'a>,)+> SystemParam<'a> for ($($t,)+) {
+            fn new(db: &'a World, commands_index: usize, ticks: SystemTicks) -> Self {
+                ($(<$t>::new(db, commands_index, ticks),)+)
+            }
+
+            fn components_mut(set: &mut HashSet<TypeDesc>) {
+                $(<$t>::components_mut(set);)+
+            }
+
+            fn components_const(set: &mut HashSet<TypeDesc>) {
+                $(<$t>::components_const(set);)+
+            }
+
+            fn resources_mut(set: &mut HashSet<TypeDesc>) {
+                $(<$t>::resources_mut(set);)+
+            }
+
+            fn resources_const(set: &mut HashSet<TypeDesc>) {
+                $(<$t>::resources_const(set);)+
+            }
+
+            fn is_non_send() -> bool {
+                false $(|| <$t>::is_non_send())+
+            }
+
+            fn conflicts() -> Vec<Conflict> {
+                let mut props = QueryProperties::default();
+                let mut conflicts = Vec::new();
+                $(add_param_conflicts::<$t>(&mut props, &mut conflicts);)+
+                conflicts
+            }
+        }
+    };
+}
+
+impl_param_tuple!(Q0);
+impl_param_tuple!(Q0, Q1);
+impl_param_tuple!(Q0, Q1, Q2);
+impl_param_tuple!(Q0, Q1, Q2, Q3);
+impl_param_tuple!(Q0, Q1, Q2, Q3, Q4);
+impl_param_tuple!(Q0, Q1, Q2, Q3, Q4, Q5);
+impl_param_tuple!(Q0, Q1, Q2, Q3, Q4, Q5, Q6);
+impl_param_tuple!(Q0, Q1, Q2, Q3, Q4, Q5, Q6, Q7);
+impl_param_tuple!(Q0, Q1, Q2, Q3, Q4, Q5, Q6, Q7, Q8);
+impl_param_tuple!(Q0, Q1, Q2, Q3, Q4, Q5, Q6, Q7, Q8, Q9);
+impl_param_tuple!(Q0, Q1, Q2, Q3, Q4, Q5, Q6, Q7, Q8, Q9, Q10);
+impl_param_tuple!(Q0, Q1, Q2, Q3, Q4, Q5, Q6, Q7, Q8, Q9, Q10, Q11);
+
+/// Run condition that returns true if resource `T` was changed since the condition last ran
+///
+/// Panics if the resource doesn't exist
+pub fn resource_changed<T: 'static>() -> impl Fn(Res<T>) -> bool + Copy {
+    |res: Res<T>| res.is_changed()
 }
 
 pub struct ErasedSystem<'a, R> {
     pub name: Cow<'a, str>,
     pub commands_index: usize,
+    /// Disabled systems are skipped by the World
+    pub enabled: bool,
+    pub(crate) last_run: AtomicU64,
     pub(crate) execute: Box<InnerSystem<'a, R>>,
-    pub(crate) components_mut: fn() -> HashSet<TypeId>,
-    pub(crate) resources_mut: fn() -> HashSet<TypeId>,
-    pub(crate) components_const: fn() -> HashSet<TypeId>,
-    pub(crate) resources_const: fn() -> HashSet<TypeId>,
+    pub(crate) components_mut: fn() -> HashSet<TypeDesc>,
+    pub(crate) resources_mut: fn() -> HashSet<TypeDesc>,
+    pub(crate) components_const: fn() -> HashSet<TypeDesc>,
+    pub(crate) resources_const: fn() -> HashSet<TypeDesc>,
+    /// Non-send systems are executed on the thread calling [[World::tick]]
+    pub(crate) non_send: bool,
+    /// Exclusive systems are executed by the World directly, instead of `execute`
+    pub(crate) exclusive: Option<Rc<ExclusiveSystem>>,
     factory: Rc<dyn Fn() -> Box<InnerSystem<'a, R>>>,
 }
 
+impl<'a, R> ErasedSystem<'a, R> {
+    /// Component and resource types this system accesses
+    pub fn properties(&self) -> QueryProperties {
+        QueryProperties {
+            comp_mut: (self.components_mut)(),
+            res_mut: (self.resources_mut)(),
+            comp_const: (self.components_const)(),
+            res_const: (self.resources_const)(),
+        }
+    }
+
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive.is_some()
+    }
+
+    pub fn is_non_send(&self) -> bool {
+        self.non_send
+    }
+
+    pub fn with_name<N: Into<Cow<'a, str>>>(mut self, name: N) -> Self {
+        self.name = name.into();
+        self
+    }
+}
+
+impl<'a> ErasedSystem<'a, ()> {
+    pub(crate) fn exclusive<F>(system: F) -> Self
+    where
+        F: Fn(&mut World) + 'static,
+    {
+        let factory: Rc<dyn Fn() -> Box<InnerSystem<'a, ()>>> = Rc::new(|| {
+            Box::new(|_world: &'a World, _commands_index, _ticks| {
+                unreachable!("Exclusive systems must be executed by the World")
+            })
+        });
+        ErasedSystem {
+            name: std::any::type_name::<F>().into(),
+            commands_index: 0,
+            enabled: true,
+            last_run: AtomicU64::new(0),
+            execute: factory(),
+            components_mut: HashSet::new,
+            resources_mut: HashSet::new,
+            components_const: HashSet::new,
+            resources_const: HashSet::new,
+            non_send: true,
+            exclusive: Some(Rc::new(system)),
+            factory,
+        }
+    }
+}
+
 unsafe impl<R> Send for ErasedSystem<'_, R> {}
 unsafe impl<R> Sync for ErasedSystem<'_, R> {}
 
@@ -139,26 +562,47 @@ impl<'a, R> Clone for ErasedSystem<'a, R> {
         Self {
             name: self.name.clone(),
             commands_index: self.commands_index,
+            enabled: self.enabled,
+            last_run: AtomicU64::new(self.last_run.load(Ordering::Relaxed)),
             execute: (self.factory)(),
             components_mut: self.components_mut,
             resources_mut: self.resources_mut,
             components_const: self.components_const,
             resources_const: self.resources_const,
+            non_send: self.non_send,
+            exclusive: self.exclusive.clone(),
             factory: self.factory.clone(),
         }
     }
 }
 
+/// `Param` is the tuple of the system's parameters, e.g. `(Query<&u32>,)` for a single parameter,
+/// so systems taking one tuple parameter and systems taking its elements are distinct
 pub trait IntoSystem<'a, Param, R> {
     fn system(self) -> ErasedSystem<'a, R>;
+
+    /// Systems are named after their type by default.
+    /// Use this method to give them a name that can be used to refer to them later, e.g. in
+    /// [[World::set_system_enabled]]
+    fn named<N: Into<Cow<'a, str>>>(self, name: N) -> ErasedSystem<'a, R>
+    where
+        Self: Sized,
+    {
+        self.system().with_name(name)
+    }
+}
+
+impl<'a, R> IntoSystem<'a, ErasedSystem<'a, R>, R> for ErasedSystem<'a, R> {
+    fn system(self) -> ErasedSystem<'a, R> {
+        self
+    }
 }
 
 macro_rules! impl_intosys_fn {
     ($($t: ident),* $(,)*) => {
-        #[allow(unused_parens)]
         #[allow(unused_mut)]
-        impl<'a, R, F, $($t: WorldQuery<'a> + 'static,)*>
-            IntoSystem<'a, ($($t),*), R> for F
+        impl<'a, R, F, $($t: SystemParam<'a> + 'static,)*>
+            IntoSystem<'a, ($($t,)*), R> for F
         where
             F: Fn($($t),*) -> R + 'static + Copy,
         {
@@ -168,16 +612,31 @@ macro_rules! impl_intosys_fn {
                     let mut _props = crate::query::QueryProperties::default();
                     // assert queries
                     $(
+                        let conflicts = <$t>::conflicts();
+                        assert!(
+                            conflicts.is_empty(),
+                            "system {} has incompatible queries!\nParameter {} has conflicting parts (part / previous parts):{}",
+                            std::any::type_name::<F>(),
+                            std::any::type_name::<$t>(),
+                            crate::query::format_conflicts(&conflicts)
+                        );
                         let p = crate::query::ensure_query_valid::<$t>();
-                        assert!(p.is_disjoint(&_props), "system {} has incompatible queries!", std::any::type_name::<F>());
+                        let conflicts = p.conflicts(&_props);
+                        assert!(
+                            conflicts.is_empty(),
+                            "system {} has incompatible queries!\nParameter {} conflicts with the previous parameters (parameter / previous):{}",
+                            std::any::type_name::<F>(),
+                            std::any::type_name::<$t>(),
+                            crate::query::format_conflicts(&conflicts)
+                        );
                         _props.extend(p);
                     )*
                 }
                 let factory: Rc<dyn Fn()-> Box<InnerSystem<'a, R>>>
                     = Rc::new(move || {
-                        Box::new(move |_world: &'a World, _commands_index| {
+                        Box::new(move |_world: &'a World, _commands_index, _ticks| {
                             (self)(
-                                $(<$t>::new(_world, _commands_index),)*
+                                $(<$t>::new(_world, _commands_index, _ticks),)*
                             )
                         })
                     });
@@ -185,6 +644,8 @@ macro_rules! impl_intosys_fn {
                     name: std::any::type_name::<F>().into(),
                     execute: factory(),
                     commands_index: 0,
+                    enabled: true,
+                    last_run: AtomicU64::new(0),
                     components_mut: || {
                         let mut res = HashSet::new();
                         $(<$t>::components_mut(&mut res);)*
@@ -205,6 +666,8 @@ macro_rules! impl_intosys_fn {
                         $(<$t>::resources_const(&mut res);)*
                         res
                     },
+                    non_send: false $(|| <$t>::is_non_send())*,
+                    exclusive: None,
                     factory,
                 }
             }
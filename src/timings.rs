@@ -0,0 +1,240 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::systems::SystemStage;
+
+/// Number of samples used for the rolling average
+pub const TIMING_WINDOW: usize = 32;
+
+/// Wall-clock timing statistics of a stage or system
+#[derive(Debug, Clone)]
+pub struct TimingStats {
+    last: Duration,
+    min: Duration,
+    max: Duration,
+    count: u64,
+    samples: [Duration; TIMING_WINDOW],
+}
+
+impl Default for TimingStats {
+    fn default() -> Self {
+        Self {
+            last: Duration::ZERO,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+            count: 0,
+            samples: [Duration::ZERO; TIMING_WINDOW],
+        }
+    }
+}
+
+impl TimingStats {
+    pub fn record(&mut self, duration: Duration) {
+        self.samples[(self.count % TIMING_WINDOW as u64) as usize] = duration;
+        self.count += 1;
+        self.last = duration;
+        self.min = self.min.min(duration);
+        self.max = self.max.max(duration);
+    }
+
+    pub fn last(&self) -> Duration {
+        self.last
+    }
+
+    /// Returns `Duration::ZERO` if nothing was recorded yet
+    pub fn min(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        self.min
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Average of the last [[TIMING_WINDOW]] samples
+    pub fn avg(&self) -> Duration {
+        let n = self.count.min(TIMING_WINDOW as u64) as u32;
+        if n == 0 {
+            return Duration::ZERO;
+        }
+        self.samples[..n as usize].iter().sum::<Duration>() / n
+    }
+
+    /// Number of samples recorded
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SystemTimings {
+    pub name: String,
+    pub stats: TimingStats,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StageTimings {
+    pub name: String,
+    /// Total time of the stage, including `should_run` systems
+    pub stats: TimingStats,
+    /// In the order the systems were added to the stage
+    pub systems: Vec<SystemTimings>,
+}
+
+/// Timings of stages, keyed by the path of the stage: the index of the top-level stage, followed
+/// by the indices of its sub-stages
+///
+/// Paths follow their stages when stages are inserted or removed, the timings of removed stages
+/// and sub-stages are dropped. One-shot stages executed by [[crate::World::run_stage]] are not
+/// kept.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    pub(crate) stages: BTreeMap<Vec<usize>, StageTimings>,
+}
+
+impl Timings {
+    pub(crate) fn record(
+        &mut self,
+        path: &[usize],
+        stage: &SystemStage,
+        stage_time: Duration,
+        system_times: &[Option<Duration>],
+    ) {
+        let timings = match self.stages.get_mut(path) {
+            Some(t) => t,
+            None => self.stages.entry(path.to_vec()).or_default(),
+        };
+        if timings.name != stage.name {
+            *timings = StageTimings {
+                name: stage.name.to_string(),
+                ..Default::default()
+            };
+        }
+        timings.stats.record(stage_time);
+        let systems = stage.systems.as_slice();
+        if timings.systems.len() != systems.len() {
+            // the stage has changed
+            timings.systems = systems
+                .iter()
+                .map(|sys| SystemTimings {
+                    name: sys.name.to_string(),
+                    stats: Default::default(),
+                })
+                .collect();
+        }
+        // systems that did not run are not recorded
+        for (timing, duration) in timings.systems.iter_mut().zip(system_times) {
+            if let Some(duration) = duration {
+                timing.stats.record(*duration);
+            }
+        }
+    }
+
+    /// Timings of the first stage named `name`, in execution order
+    pub fn stage(&self, name: &str) -> Option<&StageTimings> {
+        self.stages.values().find(|t| t.name == name)
+    }
+
+    pub fn stage_at(&self, path: &[usize]) -> Option<&StageTimings> {
+        self.stages.get(path)
+    }
+
+    /// Stages in execution order, with their paths
+    pub fn stages(&self) -> impl Iterator<Item = (&[usize], &StageTimings)> {
+        self.stages.iter().map(|(path, t)| (path.as_slice(), t))
+    }
+
+    pub fn clear(&mut self) {
+        self.stages.clear();
+    }
+
+    /// A top-level stage was inserted at `index`
+    pub(crate) fn stage_inserted(&mut self, index: usize) {
+        self.rekey(|path| {
+            if path[0] >= index {
+                path[0] += 1;
+            }
+            true
+        });
+    }
+
+    /// The top-level stage at `index` was removed
+    pub(crate) fn stage_removed(&mut self, index: usize) {
+        self.rekey(|path| {
+            if path[0] == index {
+                return false;
+            }
+            if path[0] > index {
+                path[0] -= 1;
+            }
+            true
+        });
+    }
+
+    /// The top-level stage at `index` was replaced
+    pub(crate) fn stage_replaced(&mut self, index: usize) {
+        self.stages.retain(|path, _| path[0] != index);
+    }
+
+    /// The top-level stage at `index` was modified, drop the timings of its sub-stages that no
+    /// longer exist
+    pub(crate) fn stage_changed(&mut self, index: usize, stage: &SystemStage) {
+        self.stages.retain(|path, _| {
+            if path[0] != index {
+                return true;
+            }
+            let mut stage = stage;
+            for i in &path[1..] {
+                match stage.sub_stages.get(*i) {
+                    Some(sub) => stage = &sub.stage,
+                    None => return false,
+                }
+            }
+            true
+        });
+    }
+
+    fn rekey(&mut self, mut f: impl FnMut(&mut Vec<usize>) -> bool) {
+        self.stages = std::mem::take(&mut self.stages)
+            .into_iter()
+            .filter_map(|(mut path, timings)| f(&mut path).then_some((path, timings)))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timing_stats_test() {
+        let mut stats = TimingStats::default();
+        assert_eq!(stats.min(), Duration::ZERO);
+        assert_eq!(stats.avg(), Duration::ZERO);
+
+        stats.record(Duration::from_millis(2));
+        stats.record(Duration::from_millis(4));
+        stats.record(Duration::from_millis(3));
+
+        assert_eq!(stats.last(), Duration::from_millis(3));
+        assert_eq!(stats.min(), Duration::from_millis(2));
+        assert_eq!(stats.max(), Duration::from_millis(4));
+        assert_eq!(stats.avg(), Duration::from_millis(3));
+        assert_eq!(stats.count(), 3);
+    }
+
+    #[test]
+    fn timing_average_is_rolling_test() {
+        let mut stats = TimingStats::default();
+        for _ in 0..TIMING_WINDOW {
+            stats.record(Duration::from_millis(100));
+        }
+        for _ in 0..TIMING_WINDOW {
+            stats.record(Duration::from_millis(1));
+        }
+
+        assert_eq!(stats.avg(), Duration::from_millis(1));
+        assert_eq!(stats.max(), Duration::from_millis(100));
+    }
+}
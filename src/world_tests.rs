@@ -1,7 +1,7 @@
 use commands::Commands;
 
 use crate::entity_id::EntityId;
-use crate::prelude::ResMut;
+use crate::prelude::{resource_changed, ResMut};
 use crate::query::resource_query::Res;
 use crate::query::{filters::WithOut, Query};
 
@@ -483,6 +483,33 @@ fn can_insert_bundle_test() {
     assert_eq!(a, &38);
 }
 
+#[test]
+#[should_panic(expected = "more than once")]
+fn bundle_with_duplicate_components_test() {
+    // nested bundles can't be checked by the derive
+    #[derive(Bundle)]
+    struct Health {
+        hp: u32,
+    }
+
+    #[derive(Bundle)]
+    struct Unit {
+        level: u32,
+        #[bundle]
+        health: Health,
+    }
+
+    let mut world = World::new(2);
+    let id = world.insert_entity().unwrap();
+    let _ = world.set_bundle(
+        id,
+        Unit {
+            level: 1,
+            health: Health { hp: 10 },
+        },
+    );
+}
+
 #[test]
 fn can_insert_bundle_via_command_test() {
     let mut world = World::new(2);
@@ -498,3 +525,1466 @@ fn can_insert_bundle_via_command_test() {
         assert_eq!(b, &42);
     }
 }
+
+#[test]
+fn gc_archetypes_removes_empty_archetypes_test() {
+    let mut world = World::new(4);
+
+    let id = world.insert_entity().unwrap();
+    world.set_component(id, 42i32).unwrap();
+    world.set_component(id, 42u32).unwrap();
+    world.remove_component::<i32>(id).unwrap();
+
+    // void, (i32), (i32, u32), (u32)
+    assert_eq!(world.archetypes.len(), 4);
+
+    let removed = world.gc_archetypes();
+    assert_eq!(removed, 2);
+    assert_eq!(world.archetypes.len(), 2);
+    assert!(world.archetypes.contains_key(&VOID_TY));
+
+    assert_eq!(world.get_component::<u32>(id), Some(&42));
+
+    // removed archetypes are recreated on demand
+    world.set_component(id, 69i32).unwrap();
+    assert_eq!(world.get_component::<i32>(id), Some(&69));
+    assert_eq!(world.get_component::<u32>(id), Some(&42));
+}
+
+#[test]
+fn gc_archetypes_keeps_void_archetype_test() {
+    let mut world = World::new(4);
+
+    world.gc_archetypes();
+    assert!(world.archetypes.contains_key(&VOID_TY));
+
+    let id = world.insert_entity().unwrap();
+    assert!(world.get_component::<()>(id).is_some());
+}
+
+#[test]
+fn automatic_archetype_gc_test() {
+    let mut world = World::new(4);
+    world.set_archetype_gc(Some(2));
+
+    let id = world.insert_entity().unwrap();
+    world.set_component(id, 42i32).unwrap();
+    world.delete_entity(id).unwrap();
+
+    assert_eq!(world.archetypes.len(), 2);
+    world.tick();
+    assert_eq!(world.archetypes.len(), 2);
+    world.tick();
+    assert_eq!(world.archetypes.len(), 1);
+}
+
+#[test]
+fn world_stats_test() {
+    let mut world = World::new(4);
+    world.insert_resource(42i32);
+    world.insert_non_send_resource(1u8);
+    world.insert_non_send_resource(2u16);
+
+    for i in 0..3 {
+        let id = world.insert_entity().unwrap();
+        world.set_component(id, Foo { value: i }).unwrap();
+        if i % 2 == 0 {
+            world.set_component(id, 42u64).unwrap();
+        }
+    }
+
+    let stats = world.stats();
+
+    assert_eq!(stats.resources, 1);
+    assert_eq!(stats.non_send_resources, 2);
+    assert_eq!(stats.handles.len, 3);
+    assert_eq!(
+        stats.handles.free_list_len,
+        stats.handles.capacity - stats.handles.len
+    );
+    // void, (Foo), (Foo, u64)
+    assert_eq!(stats.archetypes.len(), 3);
+    assert_eq!(stats.archetypes.iter().map(|a| a.rows).sum::<usize>(), 3);
+
+    let foo = &stats.components[std::any::type_name::<Foo>()];
+    assert_eq!(foo.archetypes, 2);
+    assert_eq!(foo.rows, 3);
+    assert_eq!(foo.bytes, 3 * std::mem::size_of::<Foo>());
+
+    let long = &stats.components[std::any::type_name::<u64>()];
+    assert_eq!(long.archetypes, 1);
+    assert_eq!(long.rows, 2);
+    assert_eq!(long.bytes, 16);
+}
+
+#[test]
+fn stage_timings_are_recorded_test() {
+    let mut world = World::new(4);
+
+    fn sys_a() {}
+    fn sys_b() {}
+
+    world.add_stage(
+        SystemStage::parallel("timed")
+            .with_system(sys_a)
+            .with_system(sys_b),
+    );
+
+    world.tick();
+    world.tick();
+
+    let stage = world.timings().stage("timed").unwrap();
+    assert_eq!(stage.stats.count(), 2);
+    assert_eq!(stage.systems.len(), 2);
+    assert!(stage.systems[0].name.ends_with("sys_a"));
+    assert!(stage.systems[1].name.ends_with("sys_b"));
+    for sys in stage.systems.iter() {
+        assert_eq!(sys.stats.count(), 2);
+        assert!(sys.stats.min() <= sys.stats.max());
+    }
+
+    world.clear_timings();
+    assert!(world.timings().stage("timed").is_none());
+}
+
+#[test]
+fn stages_with_the_same_name_have_separate_timings_test() {
+    let mut world = World::new(4);
+
+    fn sys_a() {}
+    fn sys_b() {}
+
+    world.add_stage(
+        SystemStage::serial("dup")
+            .with_system(sys_a)
+            .with_sub_stage(SystemStage::serial("dup").with_system(sys_b)),
+    );
+    world.add_stage(SystemStage::serial("dup").with_system(sys_b));
+
+    world.tick();
+
+    let paths = world
+        .timings()
+        .stages()
+        .map(|(path, t)| (path.to_vec(), t.name.as_str(), t.systems.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        [
+            (vec![0], "dup", 1),
+            (vec![0, 0], "dup", 1),
+            (vec![1], "dup", 1)
+        ]
+    );
+    let timings = world.timings();
+    assert!(timings.stage_at(&[0]).unwrap().systems[0]
+        .name
+        .ends_with("sys_a"));
+    assert!(timings.stage_at(&[0, 0]).unwrap().systems[0]
+        .name
+        .ends_with("sys_b"));
+    assert_eq!(timings.stage_at(&[1]).unwrap().stats.count(), 1);
+    assert!(timings.stage("dup").unwrap().systems[0]
+        .name
+        .ends_with("sys_a"));
+}
+
+#[test]
+fn timings_follow_inserted_and_removed_stages_test() {
+    let mut world = World::new(4);
+
+    fn sys_a() {}
+
+    world.add_stage(SystemStage::serial("a").with_system(sys_a));
+    world.add_stage(
+        SystemStage::serial("b")
+            .with_system(sys_a)
+            .with_sub_stage(SystemStage::serial("b_inner").with_system(sys_a)),
+    );
+    world.tick();
+    world.tick();
+
+    world
+        .insert_stage_before("a", SystemStage::serial("first"))
+        .unwrap();
+    world.tick();
+    {
+        let timings = world.timings();
+        assert_eq!(timings.stage_at(&[0]).unwrap().stats.count(), 1);
+        assert_eq!(timings.stage_at(&[1]).unwrap().name, "a");
+        assert_eq!(timings.stage_at(&[1]).unwrap().stats.count(), 3);
+        assert_eq!(timings.stage_at(&[2]).unwrap().stats.count(), 3);
+        assert_eq!(timings.stage_at(&[2, 0]).unwrap().name, "b_inner");
+    }
+
+    world.remove_stage("a").unwrap();
+    {
+        let timings = world.timings();
+        assert!(timings.stage("a").is_none());
+        assert_eq!(timings.stage_at(&[1]).unwrap().name, "b");
+        assert_eq!(timings.stage_at(&[1]).unwrap().stats.count(), 3);
+        assert_eq!(timings.stage_at(&[1, 0]).unwrap().stats.count(), 3);
+        assert!(timings.stage_at(&[2]).is_none());
+    }
+
+    *world.get_stage_mut("b").unwrap() = SystemStage::serial("b").with_system(sys_a);
+    assert!(world.timings().stage_at(&[1, 0]).is_none());
+
+    // a stage re-added at the same position starts from scratch
+    world.add_stage(SystemStage::serial("b").with_system(sys_a));
+    world.tick();
+    assert_eq!(world.timings().stage_at(&[2]).unwrap().stats.count(), 1);
+
+    world
+        .replace_stage("first", SystemStage::serial("first"))
+        .unwrap();
+    assert!(world.timings().stage_at(&[0]).is_none());
+
+    world.run_stage(SystemStage::serial("one-shot").with_system(sys_a));
+    assert!(world.timings().stage("one-shot").is_none());
+}
+
+#[test]
+fn explain_conflict_test() {
+    fn sys_a(_q: Query<(&mut Foo, &String)>, _r: Res<i32>) {}
+    fn sys_b(_q: Query<(&Foo, &mut String)>, _r: ResMut<i32>) {}
+    fn sys_c(_q: Query<&String>, _r: Res<i32>) {}
+
+    let conflicts = World::explain_conflict(sys_a, sys_b);
+    let conflicts = conflicts
+        .iter()
+        .map(|c| (c.kind, c.ty.name, c.lhs, c.rhs))
+        .collect::<Vec<_>>();
+
+    use crate::query::{Access, ConflictKind};
+    let mut expected = vec![
+        (
+            ConflictKind::Component,
+            std::any::type_name::<Foo>(),
+            Access::Write,
+            Access::Read,
+        ),
+        (
+            ConflictKind::Component,
+            std::any::type_name::<String>(),
+            Access::Read,
+            Access::Write,
+        ),
+    ];
+    expected.sort_by_key(|(_, name, _, _)| *name);
+    expected.push((
+        ConflictKind::Resource,
+        std::any::type_name::<i32>(),
+        Access::Read,
+        Access::Write,
+    ));
+    assert_eq!(conflicts, expected);
+
+    assert!(World::explain_conflict(sys_a, sys_c).is_empty());
+}
+
+#[test]
+fn non_send_resources_do_not_conflict_with_resources_test() {
+    use crate::query::resource_query::{NonSend, NonSendMut};
+
+    fn write_res(_r: ResMut<i32>) {}
+    fn read_non_send(_r: NonSend<i32>) {}
+    fn write_non_send(_r: NonSendMut<i32>) {}
+
+    assert!(World::explain_conflict(write_res, read_non_send).is_empty());
+    assert!(World::explain_conflict(write_res, write_non_send).is_empty());
+
+    let conflicts = World::explain_conflict(read_non_send, write_non_send);
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].kind, crate::query::ConflictKind::Resource);
+    assert_eq!(
+        conflicts[0].ty.name,
+        std::any::type_name::<NonSend<'static, i32>>()
+    );
+
+    // a system may read a resource and write the non-send resource of the same type
+    let mut world = World::new(4);
+    world.insert_resource(1i32);
+    world.insert_non_send_resource(2i32);
+    world.run_system(|r: Res<i32>, mut ns: NonSendMut<i32>| *ns += *r);
+    assert_eq!(*world.get_non_send_resource::<i32>().unwrap(), 3);
+}
+
+#[test]
+#[should_panic(expected = "resource `i32` (write / read)")]
+#[cfg(debug_assertions)]
+fn incompatible_queries_panic_lists_types_test() {
+    fn sys(_valid_query_1: Res<i32>, _valid_query_2: ResMut<i32>) {}
+
+    let mut world = World::new(1);
+
+    world.run_system(sys);
+}
+
+#[test]
+fn exclusive_system_test() {
+    fn spawn_sys(mut cmd: Commands) {
+        cmd.spawn().insert(Foo { value: 1 });
+    }
+
+    fn exclusive_sys(world: &mut World) {
+        // commands of the previous systems have been applied
+        let ids = Query::<(EntityId, &Foo)>::new(world)
+            .iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids.len(), 1);
+        for id in ids {
+            world.set_component(id, 42u32).unwrap();
+        }
+        world.insert_resource(0i32);
+    }
+
+    fn after_sys(q: Query<(&Foo, &u32)>, mut res: ResMut<i32>) {
+        assert_eq!(q.count(), 1);
+        *res += 1;
+    }
+
+    for stage in [
+        SystemStage::serial("serial"),
+        SystemStage::parallel("parallel"),
+    ] {
+        let mut world = World::new(4);
+        world.add_stage(
+            stage
+                .with_system(spawn_sys)
+                .with_exclusive_system(exclusive_sys)
+                .with_system(after_sys),
+        );
+
+        world.tick();
+
+        assert_eq!(world.get_resource::<i32>(), Some(&1));
+    }
+}
+
+#[test]
+fn named_stage_management_test() {
+    fn sys_a(mut log: ResMut<Vec<&'static str>>) {
+        log.push("a");
+    }
+    fn sys_b(mut log: ResMut<Vec<&'static str>>) {
+        log.push("b");
+    }
+    fn sys_c(mut log: ResMut<Vec<&'static str>>) {
+        log.push("c");
+    }
+    fn sys_d(mut log: ResMut<Vec<&'static str>>) {
+        log.push("d");
+    }
+
+    let mut world = World::new(4);
+    world.insert_resource(Vec::<&'static str>::new());
+
+    world.add_stage(SystemStage::serial("first").with_system(sys_a));
+    world.add_stage(SystemStage::serial("last").with_system(sys_d));
+    world
+        .insert_stage_after("first", SystemStage::serial("second").with_system(sys_b))
+        .unwrap();
+    world
+        .insert_stage_before("last", SystemStage::parallel("third").with_system(sys_c))
+        .unwrap();
+
+    assert_eq!(
+        world.stage_names().collect::<Vec<_>>(),
+        ["first", "second", "third", "last"]
+    );
+    assert!(matches!(
+        world.insert_stage_before("nope", SystemStage::serial("x")),
+        Err(WorldError::StageNotFound)
+    ));
+
+    world.tick();
+    assert_eq!(
+        world.get_resource::<Vec<&str>>().unwrap(),
+        &["a", "b", "c", "d"]
+    );
+
+    world.get_resource_mut::<Vec<&str>>().unwrap().clear();
+    world.get_stage_mut("third").unwrap().add_system(sys_a);
+    let removed = world.remove_stage("second").unwrap();
+    assert_eq!(removed.name, "second");
+    let old = world
+        .replace_stage("last", SystemStage::serial("last").with_system(sys_b))
+        .unwrap();
+    assert_eq!(old.systems.len(), 1);
+
+    world.tick();
+    assert_eq!(
+        world.get_resource::<Vec<&str>>().unwrap(),
+        &["a", "c", "a", "b"]
+    );
+
+    #[cfg(feature = "parallel")]
+    assert_eq!(world.schedule.len(), world.system_stages.len());
+}
+
+#[test]
+fn disable_system_test() {
+    use crate::systems::IntoSystem;
+
+    fn count(mut res: ResMut<i32>) {
+        *res += 1;
+    }
+    fn count_other(mut res: ResMut<u32>) {
+        *res += 1;
+    }
+
+    for stage in [
+        SystemStage::serial("serial"),
+        SystemStage::parallel("parallel"),
+    ] {
+        let mut world = World::new(4);
+        world.insert_resource(0i32);
+        world.insert_resource(0u32);
+        world.add_stage(
+            stage
+                .with_system(count.named("counter"))
+                .with_system(count_other)
+                .with_named_exclusive_system("exclusive", |w: &mut World| {
+                    *w.get_resource_mut::<u32>().unwrap() += 10;
+                }),
+        );
+
+        world.tick();
+        assert_eq!(world.get_resource::<i32>(), Some(&1));
+        assert_eq!(world.get_resource::<u32>(), Some(&11));
+
+        world.set_system_enabled("counter", false).unwrap();
+        world.set_system_enabled("exclusive", false).unwrap();
+        assert_eq!(world.is_system_enabled("counter"), Some(false));
+        world.tick();
+        assert_eq!(world.get_resource::<i32>(), Some(&1));
+        assert_eq!(world.get_resource::<u32>(), Some(&12));
+
+        world.set_system_enabled("counter", true).unwrap();
+        world.tick();
+        assert_eq!(world.get_resource::<i32>(), Some(&2));
+
+        assert!(matches!(
+            world.set_system_enabled("nope", false),
+            Err(WorldError::SystemNotFound)
+        ));
+        assert_eq!(world.is_system_enabled("nope"), None);
+    }
+}
+
+#[test]
+fn nested_looping_stages_test() {
+    fn push(name: &'static str) -> impl Fn(ResMut<Vec<&'static str>>) + Copy {
+        move |mut log: ResMut<Vec<&'static str>>| log.push(name)
+    }
+    fn spawn_one(mut cmd: Commands) {
+        cmd.spawn().insert(Foo { value: 0 });
+    }
+    fn less_than_three(q: Query<&Foo>) -> bool {
+        q.count() < 3
+    }
+
+    for stage in [SystemStage::serial("outer"), SystemStage::parallel("outer")] {
+        let mut world = World::new(4);
+        world.insert_resource(Vec::<&'static str>::new());
+        world.add_stage(
+            stage
+                .with_system(push("outer"))
+                .with_sub_stage(
+                    SystemStage::serial("times")
+                        .with_loop_count(2)
+                        .with_system(push("times")),
+                )
+                .with_sub_stage(
+                    SystemStage::parallel("while")
+                        .with_loop_while(less_than_three)
+                        .with_system(spawn_one)
+                        .with_sub_stage(SystemStage::serial("inner").with_system(push("inner"))),
+                ),
+        );
+
+        world.tick();
+
+        // the loop condition observes the entities spawned by the previous iterations
+        assert_eq!(world.num_entities(), 3);
+        assert_eq!(
+            world.get_resource::<Vec<&str>>().unwrap(),
+            &["outer", "times", "times", "inner", "inner", "inner"]
+        );
+        assert_eq!(
+            world.timings().stage("times").unwrap().systems[0]
+                .stats
+                .count(),
+            1
+        );
+
+        // the condition is false from the start
+        world.get_resource_mut::<Vec<&str>>().unwrap().clear();
+        world.tick();
+        assert_eq!(world.num_entities(), 3);
+        assert_eq!(
+            world.get_resource::<Vec<&str>>().unwrap(),
+            &["outer", "times", "times"]
+        );
+    }
+}
+
+#[test]
+fn stage_without_systems_keeps_condition_commands_test() {
+    fn spawn_and_run(mut cmd: Commands) -> bool {
+        cmd.spawn().insert(Foo { value: 0 });
+        true
+    }
+    fn spawn_while(mut cmd: Commands, q: Query<&Foo>) -> bool {
+        cmd.spawn().insert(Foo { value: 1 });
+        q.count() < 3
+    }
+
+    let mut world = World::new(8);
+    world.add_stage(
+        SystemStage::serial("conditions")
+            .with_should_run(spawn_and_run)
+            .with_sub_stage(SystemStage::serial("empty").with_loop_while(spawn_while)),
+    );
+
+    world.tick();
+
+    // 1 by should_run, 1 by each of the 2 iterations and 1 by the final check of the loop
+    assert_eq!(world.num_entities(), 4);
+}
+
+#[test]
+fn stage_loop_limit_test() {
+    fn count(mut n: ResMut<u32>) {
+        *n += 1;
+    }
+
+    let mut world = World::new(1);
+    world.insert_resource(0u32);
+    world.add_stage(
+        SystemStage::serial("forever")
+            .with_loop_while_limit(|| true, 5)
+            .with_system(count),
+    );
+
+    world.tick();
+
+    assert_eq!(world.get_resource::<u32>(), Some(&5));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn stage_mut_reschedules_sub_stages_test() {
+    fn write_a(_q: Query<&mut Foo>) {}
+    fn write_b(_q: Query<&mut Foo>) {}
+
+    let mut world = World::new(1);
+    world.add_stage(SystemStage::serial("outer").with_sub_stage(SystemStage::parallel("inner")));
+    {
+        let mut stage = world.get_stage_mut("outer").unwrap();
+        let inner = stage.sub_stages_mut().next().unwrap();
+        inner.add_system(write_a).add_system(write_b);
+    }
+
+    let graph = world.schedule_graph();
+    assert_eq!(graph.stages[0].sub_stages[0].groups, [[0], [1]]);
+    world.tick();
+}
+
+#[test]
+fn resource_change_detection_test() {
+    #[derive(Default, Clone)]
+    struct Changes(Vec<(bool, bool)>);
+
+    fn observe(res: Res<i32>, mut changes: ResMut<Changes>) {
+        changes.0.push((res.is_added(), res.is_changed()));
+    }
+    fn bump(mut res: ResMut<i32>) {
+        *res += 1;
+    }
+    fn read_only(res: ResMut<i32>) {
+        assert!(*res >= 0);
+    }
+
+    let mut world = World::new(4);
+    world.insert_resource(0i32);
+    world.insert_resource(Changes::default());
+    world.add_stage(SystemStage::serial("observe").with_system(observe));
+
+    world.tick();
+    world.tick();
+    world.run_system(read_only);
+    world.tick();
+    world.run_system(bump);
+    world.tick();
+    *world.get_resource_mut::<i32>().unwrap() = 5;
+    world.tick();
+    world.tick();
+
+    assert_eq!(
+        world.get_resource::<Changes>().unwrap().0,
+        [
+            (true, true),
+            (false, false),
+            (false, false),
+            (false, true),
+            (false, true),
+            (false, false)
+        ]
+    );
+}
+
+#[test]
+fn resource_changed_run_condition_test() {
+    fn count(mut runs: ResMut<u32>) {
+        *runs += 1;
+    }
+    fn bump(mut res: ResMut<i32>) {
+        *res += 1;
+    }
+
+    let mut world = World::new(4);
+    world.insert_resource(0i32);
+    world.insert_resource(0u32);
+    world.add_stage(
+        SystemStage::serial("changed")
+            .with_should_run(resource_changed::<i32>())
+            .with_system(count),
+    );
+
+    world.tick();
+    world.tick();
+    assert_eq!(world.get_resource::<u32>(), Some(&1));
+
+    world.run_system(bump);
+    world.tick();
+    world.tick();
+    assert_eq!(world.get_resource::<u32>(), Some(&2));
+
+    world.insert_resource(0i32);
+    world.tick();
+    assert_eq!(world.get_resource::<u32>(), Some(&3));
+}
+
+#[test]
+fn non_send_resource_test() {
+    use crate::query::resource_query::{NonSend, NonSendMut};
+    use std::{cell::Cell, rc::Rc, thread::ThreadId};
+
+    struct Runtime {
+        calls: Rc<Cell<u32>>,
+        thread: ThreadId,
+    }
+
+    fn call(rt: NonSend<Runtime>) {
+        assert_eq!(rt.thread, std::thread::current().id());
+        rt.calls.set(rt.calls.get() + 1);
+    }
+    fn replace(mut rt: NonSendMut<Runtime>) {
+        assert_eq!(rt.thread, std::thread::current().id());
+        rt.calls = Rc::new(Cell::new(rt.calls.get() * 10));
+    }
+    fn busy(mut q: Query<&mut Foo>) {
+        for foo in q.iter_mut() {
+            foo.value += 1;
+        }
+    }
+    fn busy_i32(mut res: ResMut<i32>) {
+        *res += 1;
+    }
+
+    let mut world = World::new(4);
+    world.insert_resource(0i32);
+    let id = world.insert_entity().unwrap();
+    world.set_component(id, Foo { value: 0 }).unwrap();
+    world.insert_non_send_resource(Runtime {
+        calls: Rc::new(Cell::new(0)),
+        thread: std::thread::current().id(),
+    });
+    world.add_stage(
+        SystemStage::parallel("update")
+            .with_system(busy)
+            .with_system(call)
+            .with_system(busy_i32)
+            .with_system(replace),
+    );
+
+    for _ in 0..4 {
+        world.tick();
+    }
+
+    assert_eq!(
+        world
+            .get_non_send_resource::<Runtime>()
+            .unwrap()
+            .calls
+            .get(),
+        11110
+    );
+    assert_eq!(world.get_resource::<i32>(), Some(&4));
+    assert!(world.remove_non_send_resource::<Runtime>().is_some());
+    assert!(world.get_non_send_resource::<Runtime>().is_none());
+}
+
+#[test]
+fn non_send_resource_change_detection_test() {
+    use crate::query::resource_query::{NonSend, NonSendMut};
+    use std::rc::Rc;
+
+    #[derive(Default, Clone)]
+    struct Changes(Vec<(bool, bool)>);
+
+    fn observe(res: NonSend<Rc<i32>>, mut changes: ResMut<Changes>) {
+        changes.0.push((res.is_added(), res.is_changed()));
+    }
+    fn bump(mut res: NonSendMut<Rc<i32>>) {
+        *res = Rc::new(**res + 1);
+    }
+    fn read_only(res: NonSendMut<Rc<i32>>) {
+        assert!(**res >= 0);
+    }
+
+    let mut world = World::new(4);
+    world.insert_non_send_resource(Rc::new(0i32));
+    world.insert_resource(Changes::default());
+    world.add_stage(SystemStage::serial("observe").with_system(observe));
+
+    world.tick();
+    world.tick();
+    world.run_system(read_only);
+    world.tick();
+    world.run_system(bump);
+    world.tick();
+    *world.get_non_send_resource_mut::<Rc<i32>>().unwrap() = Rc::new(5);
+    world.tick();
+    world.tick();
+
+    assert_eq!(
+        world.get_resource::<Changes>().unwrap().0,
+        [
+            (true, true),
+            (false, false),
+            (false, false),
+            (false, true),
+            (false, true),
+            (false, false)
+        ]
+    );
+}
+
+#[test]
+fn non_send_resource_dropped_on_another_thread_is_leaked_test() {
+    use std::rc::Rc;
+
+    let rc = Rc::new(42);
+    let mut world = World::new(4);
+    world.insert_non_send_resource(Rc::clone(&rc));
+
+    // the World is Send even if its non-send resources are not
+    std::thread::spawn(move || {
+        drop(world);
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(Rc::strong_count(&rc), 2);
+}
+
+#[test]
+fn resource_scope_test() {
+    #[derive(Clone)]
+    struct Generator {
+        next: i32,
+    }
+
+    let mut world = World::new(4);
+    world.insert_resource(Generator { next: 0 });
+
+    let spawned = world
+        .resource_scope(|world, gen: &mut Generator| {
+            assert!(world.get_resource::<Generator>().is_none());
+            for _ in 0..3 {
+                let id = world.insert_entity().unwrap();
+                world.set_component(id, Foo { value: gen.next }).unwrap();
+                gen.next += 1;
+            }
+            3
+        })
+        .unwrap();
+    assert_eq!(spawned, 3);
+    assert_eq!(world.num_entities(), 3);
+    assert_eq!(world.get_resource::<Generator>().unwrap().next, 3);
+
+    // the resource is reinserted on panic
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        world
+            .resource_scope(|_world, gen: &mut Generator| {
+                gen.next = 10;
+                panic!("boom");
+            })
+            .unwrap();
+    }));
+    assert!(result.is_err());
+    assert_eq!(world.get_resource::<Generator>().unwrap().next, 10);
+
+    assert!(matches!(
+        world.resource_scope(|_, _: &mut u64| {}),
+        Err(WorldError::ResourceNotFound)
+    ));
+}
+
+#[test]
+fn world_query_test() {
+    let mut world = World::new(4);
+    for i in 0..3 {
+        let id = world.insert_entity().unwrap();
+        world.set_component(id, Foo { value: i }).unwrap();
+        if i == 0 {
+            world.set_component(id, "zero".to_string()).unwrap();
+        }
+    }
+
+    for foo in world.query_mut::<&mut Foo>().iter_mut() {
+        foo.value *= 10;
+    }
+
+    let q = world.query::<(EntityId, &Foo)>();
+    assert_eq!(q.count(), 3);
+    let mut values = q.iter().map(|(_, foo)| foo.value).collect::<Vec<_>>();
+    values.sort_unstable();
+    assert_eq!(values, [0, 10, 20]);
+
+    let id = world
+        .query::<(EntityId, &String)>()
+        .iter()
+        .next()
+        .unwrap()
+        .0;
+    world.query_mut::<&mut Foo>().fetch_mut(id).unwrap().value = 42;
+    assert_eq!(world.get_component::<Foo>(id).unwrap().value, 42);
+
+    assert_eq!(world.query_filtered::<&Foo, WithOut<String>>().count(), 2);
+    assert!(world
+        .query_filtered_mut::<&mut Foo, WithOut<String>>()
+        .fetch_mut(id)
+        .is_none());
+}
+
+#[test]
+#[should_panic]
+fn world_query_mut_aliasing_panics_test() {
+    let mut world = World::new(4);
+    world.query_mut::<(&mut Foo, &Foo)>();
+}
+
+#[test]
+fn fetch_many_mut_test() {
+    use crate::query::QueryFetchError;
+
+    let mut world = World::new(4);
+    let attacker = world.insert_entity().unwrap();
+    world.set_component(attacker, Foo { value: 10 }).unwrap();
+    let target = world.insert_entity().unwrap();
+    world.set_component(target, Foo { value: 100 }).unwrap();
+    world.set_component(target, "target".to_string()).unwrap();
+    let other = world.insert_entity().unwrap();
+    let deleted = world.insert_entity().unwrap();
+    world.delete_entity(deleted).unwrap();
+
+    world.run_system(move |mut q: Query<&mut Foo>| {
+        let [a, t] = q.fetch_many_mut([attacker, target]).unwrap();
+        t.value -= a.value;
+
+        assert_eq!(
+            q.fetch_many_mut([attacker, attacker]).err(),
+            Some(QueryFetchError::Duplicate(attacker))
+        );
+        assert_eq!(
+            q.fetch_many_mut([attacker, other]).err(),
+            Some(QueryFetchError::NotMatched(other))
+        );
+        assert_eq!(
+            q.fetch_many([deleted]).err(),
+            Some(QueryFetchError::EntityNotFound(deleted))
+        );
+    });
+    assert_eq!(world.get_component::<Foo>(target).unwrap().value, 90);
+
+    world.run_system(move |mut q: Query<&mut Foo>| {
+        for foo in q.iter_many_mut([target, other, target, attacker]) {
+            foo.value += 1;
+        }
+        let values = q
+            .iter_many([attacker, deleted, target])
+            .map(|foo| foo.value)
+            .collect::<Vec<_>>();
+        assert_eq!(values, [11, 91]);
+    });
+}
+
+#[test]
+fn component_index_test() {
+    use crate::index::Index;
+
+    #[derive(Clone, Copy)]
+    struct Hex {
+        q: i32,
+        r: i32,
+    }
+    #[derive(Clone, Copy)]
+    struct Owner(u32);
+
+    let mut world = World::new(8);
+    world.add_unique_index(|hex: &Hex| (hex.q, hex.r));
+    world.add_index(|owner: &Owner| owner.0);
+
+    let mut ids = Vec::new();
+    for i in 0..4 {
+        let id = world.insert_entity().unwrap();
+        world.set_component(id, Hex { q: i, r: -i }).unwrap();
+        world.set_component(id, Owner(i as u32 % 2)).unwrap();
+        ids.push(id);
+    }
+
+    {
+        let hexes = world.get_index::<Hex, (i32, i32)>().unwrap();
+        assert_eq!(hexes.get(&(2, -2)), Some(ids[2]));
+        assert_eq!(hexes.get(&(2, 2)), None);
+        let owners = world.get_index::<Owner, u32>().unwrap();
+        assert_eq!(owners.get_all(&1), &[ids[1], ids[3]]);
+    }
+
+    // mutation in a system
+    fn shift(mut q: Query<&mut Hex>) {
+        for hex in q.iter_mut() {
+            hex.q += 10;
+        }
+    }
+    world.run_system(shift);
+    fn lookup(hexes: Index<Hex, (i32, i32)>, mut found: ResMut<Vec<EntityId>>) {
+        found.extend(hexes.get(&(12, -2)));
+        assert!(!hexes.contains_key(&(2, -2)));
+    }
+    world.insert_resource(Vec::<EntityId>::new());
+    world.run_system(lookup);
+    assert_eq!(world.get_resource::<Vec<EntityId>>().unwrap(), &[ids[2]]);
+
+    // insert, remove and delete
+    world.set_component(ids[0], Owner(1)).unwrap();
+    world.remove_component::<Owner>(ids[1]).unwrap();
+    world.delete_entity(ids[3]).unwrap();
+    let owners = world.get_index::<Owner, u32>().unwrap();
+    assert_eq!(owners.get_all(&1), &[ids[0]]);
+    assert_eq!(owners.get_all(&0), &[ids[2]]);
+
+    // mutation via World::query_mut
+    for hex in world.query_mut::<&mut Hex>().iter_mut() {
+        hex.r = 0;
+    }
+    let hexes = world.get_index::<Hex, (i32, i32)>().unwrap();
+    assert_eq!(hexes.get(&(10, 0)), Some(ids[0]));
+    assert_eq!(hexes.len(), 3);
+
+    assert!(world.remove_index::<Hex, (i32, i32)>());
+    assert!(world.get_index::<Hex, (i32, i32)>().is_none());
+}
+
+#[test]
+fn component_index_updates_mutated_rows_test() {
+    #[derive(Clone, Copy)]
+    struct Tile(i32);
+
+    let mut world = World::new(8);
+    world.add_unique_index(|tile: &Tile| tile.0);
+    let ids = (0..4)
+        .map(|i| {
+            let id = world.insert_entity().unwrap();
+            world.set_component(id, Tile(i)).unwrap();
+            id
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        world.get_index::<Tile, i32>().unwrap().get(&3),
+        Some(ids[3])
+    );
+
+    // single entity handed out mutably
+    fn swap_first(mut q: Query<&mut Tile>, ids: Res<Vec<EntityId>>) {
+        let [a, b] = q.fetch_many_mut([ids[0], ids[1]]).unwrap();
+        std::mem::swap(a, b);
+    }
+    world.insert_resource(ids.clone());
+    world.run_system(swap_first);
+    {
+        let tiles = world.get_index::<Tile, i32>().unwrap();
+        assert_eq!(tiles.get(&0), Some(ids[1]));
+        assert_eq!(tiles.get(&1), Some(ids[0]));
+    }
+
+    // rows mutated in an archetype the entity left before the index was read
+    for tile in world.query_mut::<&mut Tile>().iter_mut() {
+        tile.0 += 10;
+    }
+    world.set_component(ids[2], 0u8).unwrap();
+    world.delete_entity(ids[3]).unwrap();
+    let tiles = world.get_index::<Tile, i32>().unwrap();
+    assert_eq!(tiles.get(&12), Some(ids[2]));
+    assert_eq!(tiles.get(&10), Some(ids[1]));
+    assert!(!tiles.contains_key(&2));
+    assert!(!tiles.contains_key(&13));
+    assert_eq!(tiles.len(), 3);
+}
+
+#[test]
+fn component_index_updated_by_commands_test() {
+    #[derive(Clone, Copy)]
+    struct Tile(i32);
+
+    let mut world = World::new(8);
+    world.add_unique_index(|tile: &Tile| tile.0);
+    let id = world.insert_entity().unwrap();
+    world.set_component(id, Tile(1)).unwrap();
+    assert_eq!(world.get_index::<Tile, i32>().unwrap().get(&1), Some(id));
+
+    world
+        .ensure_commands()
+        .entity(id)
+        .update::<Tile>(|tile| tile.0 = 2);
+    world.apply_commands().unwrap();
+    let tiles = world.get_index::<Tile, i32>().unwrap();
+    assert_eq!(tiles.get(&2), Some(id));
+    assert!(!tiles.contains_key(&1));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn command_log_replay_test() {
+    #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Pos(i32, i32);
+
+    fn spawn_sys(mut cmd: Commands, q: Query<EntityId>) {
+        if q.count() < 3 {
+            cmd.spawn().insert(Pos(1, 2)).insert(0u8);
+        }
+        cmd.insert_resource(q.count() as u64);
+    }
+
+    fn move_sys(mut cmd: Commands, q: Query<(EntityId, &Pos)>) {
+        for (id, _) in q.iter() {
+            cmd.entity(id).update::<Pos>(|p| p.0 += 1);
+        }
+    }
+
+    fn setup() -> World {
+        let mut world = World::new(16);
+        world.register_logged_component::<Pos>();
+        world.register_logged_resource::<u64>();
+        world.add_stage(
+            SystemStage::serial("update")
+                .with_system(spawn_sys)
+                .with_system(move_sys),
+        );
+        world
+    }
+
+    let mut world = setup();
+    world.start_command_log();
+    for _ in 0..4 {
+        world.tick();
+    }
+    let first = world.query::<EntityId>().iter().next().unwrap();
+    world.ensure_commands().entity(first).remove::<Pos>();
+    world.apply_commands().unwrap();
+    let log = world.take_command_log().unwrap();
+    assert!(world.command_log().is_none());
+
+    assert_eq!(log.ticks.len(), 4);
+    // spawn, set Pos, insert resource; u8 was not registered
+    assert_eq!(log.ticks[0].commands.len(), 3);
+    assert!(matches!(
+        log.ticks[3].commands.last().unwrap(),
+        command_log::LoggedCommand::RemoveComponent { entity, .. } if *entity == first
+    ));
+
+    let payload = bincode::serialize(&log).unwrap();
+    let log: command_log::CommandLog = bincode::deserialize(&payload).unwrap();
+
+    let mut replayed = setup();
+    replayed.replay_command_log(&log).unwrap();
+
+    let mut expected = world
+        .query::<(EntityId, &Pos)>()
+        .iter()
+        .map(|(id, p)| (id, p.clone()))
+        .collect::<Vec<_>>();
+    let mut actual = replayed
+        .query::<(EntityId, &Pos)>()
+        .iter()
+        .map(|(id, p)| (id, p.clone()))
+        .collect::<Vec<_>>();
+    expected.sort_unstable_by_key(|(id, _)| *id);
+    actual.sort_unstable_by_key(|(id, _)| *id);
+    assert_eq!(expected.len(), 2);
+    assert_eq!(expected, actual);
+    assert_eq!(replayed.num_entities(), 3);
+    assert!(replayed.get_component::<u8>(first).is_none());
+    assert_eq!(world.get_resource::<u64>(), replayed.get_resource::<u64>());
+
+    // the replayed world already has the recorded entities
+    assert!(matches!(
+        replayed.replay_command_log(&log),
+        Err(command_log::ReplayError::EntityIdMismatch { .. })
+    ));
+}
+
+#[test]
+fn state_hash_test() {
+    fn setup() -> (World, EntityId, EntityId) {
+        let mut world = World::new(4);
+        world.register_hashed_component::<u32>();
+        let a = world.insert_entity().unwrap();
+        world.set_component(a, 1u32).unwrap();
+        let b = world.insert_entity().unwrap();
+        world.set_component(b, 2u32).unwrap();
+        world.set_component(b, "b".to_string()).unwrap();
+        (world, a, b)
+    }
+
+    let (w1, _, _) = setup();
+    let (mut w2, a, b) = setup();
+    assert_eq!(w1.state_hash(), w2.state_hash());
+
+    // moves `a` to another row and leaves an empty archetype behind
+    w2.set_component(a, 0u64).unwrap();
+    w2.remove_component::<u64>(a).unwrap();
+    assert_eq!(w1.state_hash(), w2.state_hash());
+
+    // unregistered components only contribute their type
+    w2.set_component(b, "bb".to_string()).unwrap();
+    assert_eq!(w1.state_hash(), w2.state_hash());
+
+    w2.set_component(a, 3u32).unwrap();
+    let (h1, h2) = (w1.state_hash(), w2.state_hash());
+    assert_ne!(h1.hash, h2.hash);
+    assert_eq!(h1.handles, h2.handles);
+    let diverging = h1.diverging_archetypes(&h2);
+    assert_eq!(diverging.len(), 1);
+    assert!(!diverging[0].iter().any(|ty| ty.contains("String")));
+
+    // same components, different entity ids
+    let (mut w3, _, b) = setup();
+    w3.delete_entity(b).unwrap();
+    let b = w3.insert_entity().unwrap();
+    w3.set_component(b, 2u32).unwrap();
+    w3.set_component(b, "b".to_string()).unwrap();
+    let h3 = w3.state_hash();
+    assert_ne!(h1.handles, h3.handles);
+    assert_eq!(h1.diverging_archetypes(&h3).len(), 1);
+}
+
+#[cfg(feature = "clone")]
+#[test]
+fn checkpoint_rollback_test() {
+    #[derive(Clone)]
+    struct Turn(u32);
+
+    fn spawn_sys(mut cmd: Commands, mut turn: ResMut<Turn>) {
+        turn.0 += 1;
+        cmd.spawn().insert(turn.0);
+    }
+
+    let mut world = World::new(4);
+    world.insert_resource(Turn(0));
+    world.add_stage(SystemStage::serial("update").with_system(spawn_sys));
+    world.set_checkpoint_capacity(3);
+
+    for _ in 0..5 {
+        world.checkpoint();
+        world.tick();
+    }
+    assert_eq!(world.num_checkpoints(), 3);
+    assert_eq!(world.num_entities(), 5);
+    let hash = world.state_hash();
+
+    // restore the state before the last tick
+    world.rollback(1).unwrap();
+    assert_eq!(world.get_resource::<Turn>().unwrap().0, 4);
+    assert_eq!(world.num_entities(), 4);
+    assert_eq!(world.num_checkpoints(), 2);
+
+    // re-simulating reaches the same state, including the allocated ids
+    world.tick();
+    assert_eq!(world.state_hash().handles, hash.handles);
+    assert_eq!(world.query::<&u32>().iter().copied().max(), Some(5));
+
+    world.rollback(2).unwrap();
+    assert_eq!(world.get_resource::<Turn>().unwrap().0, 2);
+    assert_eq!(world.query::<&u32>().count(), 2);
+    assert_eq!(world.num_checkpoints(), 0);
+
+    assert!(matches!(
+        world.rollback(1),
+        Err(WorldError::CheckpointNotFound)
+    ));
+}
+
+#[cfg(feature = "clone")]
+#[test]
+fn rollback_restores_resources_and_ids_test() {
+    let mut world = World::new(4);
+    world.insert_resource(1i32);
+    let ids = (0..3)
+        .map(|_| world.insert_entity().unwrap())
+        .collect::<Vec<_>>();
+    world.checkpoint();
+
+    world.remove_resource::<i32>();
+    world.insert_resource(2u32);
+    world.delete_entity(ids[1]).unwrap();
+    let reused = world.insert_entity().unwrap();
+    assert!(!world.is_id_valid(ids[1]));
+    {
+        let mut cmd = world.ensure_commands();
+        cmd.spawn().insert(3u8);
+        cmd.insert_resource(4u64);
+    }
+
+    world.rollback(1).unwrap();
+    assert_eq!(world.get_resource::<i32>(), Some(&1));
+    assert!(world.get_resource::<u32>().is_none());
+    assert!(ids.iter().all(|id| world.is_id_valid(*id)));
+    assert!(!world.is_id_valid(reused));
+    assert_eq!(world.num_entities(), 3);
+
+    // commands issued before the rollback are discarded
+    world.apply_commands().unwrap();
+    assert_eq!(world.num_entities(), 3);
+    assert!(world.get_resource::<u64>().is_none());
+
+    // deleting the same entity again reuses the same id
+    world.delete_entity(ids[1]).unwrap();
+    assert_eq!(world.insert_entity().unwrap(), reused);
+}
+
+#[cfg(feature = "clone")]
+#[test]
+fn fork_copies_on_write_test() {
+    fn first<T: Component>(world: &World) -> *const T {
+        world.query::<&T>().iter().next().unwrap()
+    }
+
+    let mut world = World::new(4);
+    for i in 0..3u32 {
+        let id = world.insert_entity().unwrap();
+        world.set_component(id, i).unwrap();
+        world.set_component(id, i as u64).unwrap();
+    }
+    world.insert_resource(42i32);
+
+    let mut fork = world.fork();
+    assert_eq!(first::<u32>(&world), first::<u32>(&fork));
+    assert_eq!(first::<u64>(&world), first::<u64>(&fork));
+    assert!(std::ptr::eq(
+        world.get_resource::<i32>().unwrap(),
+        fork.get_resource::<i32>().unwrap()
+    ));
+
+    for i in fork.query_mut::<&mut u32>().iter_mut() {
+        *i += 10;
+    }
+    *fork.get_resource_mut::<i32>().unwrap() = 0;
+
+    // only the mutated column was copied
+    assert_ne!(first::<u32>(&world), first::<u32>(&fork));
+    assert_eq!(first::<u64>(&world), first::<u64>(&fork));
+    let mut parent = world.query::<&u32>().iter().copied().collect::<Vec<_>>();
+    parent.sort_unstable();
+    assert_eq!(parent, [0, 1, 2]);
+    assert_eq!(fork.query::<&u32>().iter().copied().min(), Some(10));
+    assert_eq!(world.get_resource::<i32>(), Some(&42));
+    assert_eq!(fork.get_resource::<i32>(), Some(&0));
+
+    // structural changes copy the archetype's columns
+    let id = world.query::<EntityId>().iter().next().unwrap();
+    world.delete_entity(id).unwrap();
+    assert_eq!(world.query::<&u64>().count(), 2);
+    assert_eq!(fork.query::<&u64>().count(), 3);
+    assert_eq!(fork.get_component::<u64>(id), Some(&(id.index() as u64)));
+
+    let fork = world.fork();
+    drop(world);
+    assert_eq!(fork.query::<&u64>().count(), 2);
+}
+
+#[cfg(feature = "clone")]
+#[test]
+fn fork_mutated_by_commands_and_systems_test() {
+    fn bump(mut q: Query<(EntityId, &mut u32)>, mut cmd: Commands) {
+        for (id, i) in q.iter_mut() {
+            *i += 10;
+            cmd.entity(id).update::<u64>(|u| *u += 100);
+        }
+    }
+
+    let mut world = World::new(4);
+    let mut ids = Vec::new();
+    for i in 0..3u32 {
+        let id = world.insert_entity().unwrap();
+        world.set_component(id, i).unwrap();
+        world.set_component(id, i as u64).unwrap();
+        ids.push(id);
+    }
+    world.add_stage(SystemStage::serial("update").with_system(bump));
+
+    let mut fork = world.fork();
+    fork.tick();
+    fork.insert_resource(ids.clone());
+    fork.run_system(|mut q: Query<&mut u32>, ids: Res<Vec<EntityId>>| {
+        *q.fetch_mut(ids[0]).unwrap() = 1000;
+    });
+
+    let values = |world: &World| {
+        ids.iter()
+            .map(|id| {
+                (
+                    *world.get_component::<u32>(*id).unwrap(),
+                    *world.get_component::<u64>(*id).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(values(&world), [(0, 0), (1, 1), (2, 2)]);
+    assert_eq!(values(&fork), [(1000, 100), (11, 101), (12, 102)]);
+
+    // and the other way around
+    world.tick();
+    assert_eq!(values(&world), [(10, 100), (11, 101), (12, 102)]);
+    assert_eq!(values(&fork), [(1000, 100), (11, 101), (12, 102)]);
+}
+
+#[test]
+fn derived_bundle_test() {
+    #[derive(Bundle)]
+    struct Position<T> {
+        pos: [T; 2],
+    }
+
+    #[derive(Bundle)]
+    struct Bot {
+        name: String,
+        hp: u32,
+        #[bundle]
+        position: Position<i32>,
+    }
+
+    let mut world = World::new(4);
+    let a = world.insert_entity().unwrap();
+    world
+        .set_bundle(
+            a,
+            Bot {
+                name: "a".to_string(),
+                hp: 10,
+                position: Position { pos: [1, 2] },
+            },
+        )
+        .unwrap();
+
+    {
+        let mut cmd = world.ensure_commands();
+        cmd.spawn().insert_bundle(Bot {
+            name: "b".to_string(),
+            hp: 20,
+            position: Position { pos: [3, 4] },
+        });
+    }
+    world.apply_commands().unwrap();
+
+    // both entities are in the same archetype as the equivalent tuple
+    assert_eq!(
+        Bot::compute_hash(VOID_TY),
+        <(String, u32, [i32; 2])>::compute_hash(VOID_TY)
+    );
+    let mut components = Vec::new();
+    Bot::component_ids(&mut components);
+    assert_eq!(
+        components,
+        [
+            std::any::TypeId::of::<String>(),
+            std::any::TypeId::of::<u32>(),
+            std::any::TypeId::of::<[i32; 2]>()
+        ]
+    );
+    assert_eq!(world.num_entities(), 2);
+    let mut bots = world
+        .query::<(&String, &u32, &[i32; 2])>()
+        .iter()
+        .map(|(name, hp, pos)| (name.clone(), *hp, *pos))
+        .collect::<Vec<_>>();
+    bots.sort_by_key(|(_, hp, _)| *hp);
+    assert_eq!(
+        bots,
+        [("a".to_string(), 10, [1, 2]), ("b".to_string(), 20, [3, 4])]
+    );
+}
+
+#[derive(crate::systems::SystemParam)]
+struct Movement<'a> {
+    positions: Query<&'a mut [i32; 2]>,
+    tagged: Query<&'a u32, WithOut<String>>,
+    speed: Res<'a, i32>,
+}
+
+#[test]
+fn derived_system_param_test() {
+    use crate::query::TypeDesc;
+    use crate::systems::IntoSystem;
+
+    fn movement(mut m: Movement, mut count: ResMut<u64>) {
+        for pos in m.positions.iter_mut() {
+            pos[0] += *m.speed;
+        }
+        *count += m.tagged.count() as u64;
+    }
+
+    let mut world = World::new(4);
+    let id = world.insert_entity().unwrap();
+    world.set_component(id, [1, 2]).unwrap();
+    world.set_component(id, 1u32).unwrap();
+    world.insert_resource(3i32);
+    world.insert_resource(0u64);
+
+    let props = movement.system().properties();
+    assert!(props.comp_mut.contains(&TypeDesc::of::<[i32; 2]>()));
+    assert!(props.res_const.contains(&TypeDesc::of::<i32>()));
+    assert!(props.res_mut.contains(&TypeDesc::of::<u64>()));
+
+    world.run_system(movement);
+    assert_eq!(world.get_component::<[i32; 2]>(id), Some(&[4, 2]));
+    assert_eq!(world.get_resource::<u64>(), Some(&1));
+
+    // tuples group parameters
+    let total = world.run_system(|(a, b): (Res<i32>, Res<u64>), q: Query<&u32>| {
+        *a as u64 + *b + q.count() as u64
+    });
+    assert_eq!(total, 5);
+}
+
+#[test]
+#[should_panic(expected = "conflicting parts")]
+#[cfg(debug_assertions)]
+fn conflicting_system_param_panics_test() {
+    #[derive(crate::systems::SystemParam)]
+    struct Invalid<'a> {
+        _a: Res<'a, i32>,
+        _b: ResMut<'a, i32>,
+    }
+
+    fn sys(_invalid: Invalid) {}
+
+    let mut world = World::new(1);
+
+    world.run_system(sys);
+}